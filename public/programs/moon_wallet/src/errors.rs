@@ -83,4 +83,85 @@ pub enum WalletError {
     
     #[msg("Chủ sở hữu tài khoản không hợp lệ")]
     InvalidOwner,
+    
+    #[msg("Authenticator data không hợp lệ")]
+    InvalidAuthenticatorData,
+    
+    #[msg("RP ID hash không khớp với ví")]
+    RpIdMismatch,
+    
+    #[msg("Người dùng chưa được xác thực trên authenticator")]
+    UserNotVerified,
+    
+    #[msg("Bộ đếm chữ ký của authenticator không tăng")]
+    SignCountReplay,
+    
+    #[msg("Client data JSON không hợp lệ")]
+    InvalidClientData,
+    
+    #[msg("Challenge không khớp với message mong đợi")]
+    ChallengeMismatch,
+    
+    #[msg("Thiếu chữ ký ed25519 của guardian")]
+    GuardianSignerMissing,
+    
+    #[msg("Thiếu WebAuthn assertion của guardian")]
+    WebAuthnAssertionMissing,
+    
+    #[msg("Đề xuất đã hết hạn")]
+    ProposalExpired,
+    
+    #[msg("Đề xuất chưa hết hạn")]
+    ProposalNotExpired,
+    
+    #[msg("Đề xuất vẫn đang chờ xử lý")]
+    ProposalNotFinalized,
+    
+    #[msg("Tài khoản chữ ký của đề xuất không hợp lệ")]
+    InvalidProposalAccount,
+    
+    #[msg("Không thể xoá owner cuối cùng của ví")]
+    LastOwner,
+    
+    #[msg("Chưa hết thời gian chờ khôi phục")]
+    RecoveryDelayNotElapsed,
+    
+    #[msg("Guardian đã phê duyệt yêu cầu này")]
+    AlreadyApproved,
+    
+    #[msg("Chưa đủ guardian phê duyệt khôi phục")]
+    InsufficientRecoveryApprovals,
+    
+    #[msg("Vượt quá hạn mức chi tiêu")]
+    SpendingLimitExceeded,
+    
+    #[msg("Địa chỉ đích không nằm trong danh sách cho phép")]
+    DestinationNotAllowed,
+    
+    #[msg("Chuyển tới địa chỉ ngoài sổ địa chỉ cần thêm chữ ký")]
+    ElevatedThresholdNotMet,
+    
+    #[msg("Token account nhận không khớp với đề xuất")]
+    InvalidRecipientTokenAccount,
+    
+    #[msg("Nội dung đề xuất không khớp với nội dung đã phê duyệt")]
+    ProposalContentsMismatch,
+    
+    #[msg("Guardian không có quyền thực hiện thao tác này")]
+    MissingPermission,
+    
+    #[msg("Quyền của guardian không hợp lệ")]
+    InvalidPermissions,
+    
+    #[msg("Trọng số guardian không hợp lệ")]
+    InvalidWeight,
+    
+    #[msg("Yêu cầu khôi phục đã hết hạn")]
+    RecoveryExpired,
+    
+    #[msg("Yêu cầu khôi phục chưa hết hạn")]
+    RecoveryNotExpired,
+    
+    #[msg("Guardian đã từ chối đề xuất này")]
+    AlreadyRejected,
 }
\ No newline at end of file
@@ -0,0 +1,126 @@
+use anchor_lang::prelude::*;
+
+#[event]
+pub struct WalletInitialized {
+    pub multisig: Pubkey,
+    pub owner: Pubkey,
+    pub threshold: u8,
+    pub credential_id: String,
+}
+
+#[event]
+pub struct GuardianAdded {
+    pub multisig: Pubkey,
+    pub guardian_id: u64,
+    pub is_owner: bool,
+}
+
+#[event]
+pub struct GuardianRemoved {
+    pub multisig: Pubkey,
+    pub guardian_id: u64,
+}
+
+#[event]
+pub struct GuardianWeightChanged {
+    pub multisig: Pubkey,
+    pub guardian_id: u64,
+    pub weight: u8,
+}
+
+#[event]
+pub struct GuardianStatusChanged {
+    pub multisig: Pubkey,
+    pub guardian_id: u64,
+    pub is_active: bool,
+}
+
+#[event]
+pub struct RecoveryInitiated {
+    pub multisig: Pubkey,
+    pub nonce: u64,
+    pub old_guardian_id: u64,
+    pub new_guardian_id: u64,
+    pub executable_at: i64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct RecoveryApproved {
+    pub multisig: Pubkey,
+    pub nonce: u64,
+    pub guardian_id: u64,
+    pub approvals: u8,
+    pub required_approvals: u8,
+}
+
+#[event]
+pub struct RecoveryCancelled {
+    pub multisig: Pubkey,
+    pub nonce: u64,
+    pub owner_guardian_id: u64,
+}
+
+#[event]
+pub struct RecoveryExpired {
+    pub multisig: Pubkey,
+    pub nonce: u64,
+}
+
+#[event]
+pub struct RecoveryCompleted {
+    pub multisig: Pubkey,
+    pub nonce: u64,
+    pub old_guardian_id: u64,
+    pub new_guardian_id: u64,
+}
+
+#[event]
+pub struct ProposalCreated {
+    pub multisig: Pubkey,
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    pub action_count: u8,
+    pub contents_hash: [u8; 32],
+    pub required_signatures: u8,
+    pub required_weight: u16,
+    pub expires_at: Option<i64>,
+}
+
+#[event]
+pub struct ProposalApproved {
+    pub multisig: Pubkey,
+    pub proposal_id: u64,
+    pub guardian_id: u64,
+    pub signatures_count: u8,
+    pub required_signatures: u8,
+    pub approval_weight: u16,
+    pub required_weight: u16,
+}
+
+#[event]
+pub struct ProposalRejected {
+    pub multisig: Pubkey,
+    pub proposal_id: u64,
+    pub guardian_id: u64,
+    pub rejections_count: u8,
+    pub required_rejections: u8,
+    /// true khi lượt từ chối này làm đề xuất bị bác bỏ
+    pub finalized: bool,
+}
+
+#[event]
+pub struct ProposalExecuted {
+    pub multisig: Pubkey,
+    pub proposal_id: u64,
+    pub executed_actions: u8,
+    pub total_actions: u8,
+    /// false khi đề xuất còn hành động chưa thực thi
+    pub completed: bool,
+}
+
+#[event]
+pub struct ProposalExpired {
+    pub multisig: Pubkey,
+    pub proposal_id: u64,
+}
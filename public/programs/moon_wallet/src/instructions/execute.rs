@@ -0,0 +1,1236 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::system_program::{self, Allocate, Assign, CreateAccount, Transfer};
+use crate::state::*;
+use crate::errors::*;
+use crate::events::*;
+use crate::instructions::wallet::{multisig_seed, MULTISIG_SEED_VERSION};
+use crate::instructions::guardian::verify_guardian_auth;
+use crate::instructions::proposal::to_hex;
+use crate::webauthn::WebAuthnAssertion;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions},
+    onchain::{invoke_transfer_checked, invoke_transfer_checked_with_fee},
+};
+use anchor_spl::associated_token::{self, get_associated_token_address_with_program_id, AssociatedToken};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+
+#[derive(Accounts)]
+#[instruction(proposal_id: u64, executor_guardian_id: u64)]
+pub struct ExecuteProposal<'info> {
+    #[account(mut)]
+    pub multisig: Account<'info, MultiSigWallet>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"proposal".as_ref(),
+            multisig.key().as_ref(),
+            &proposal_id.to_le_bytes()
+        ],
+        bump = proposal.bump,
+        constraint = matches!(
+            proposal.status,
+            ProposalStatus::Pending | ProposalStatus::Executing
+        ) @ WalletError::InvalidOperation,
+        constraint = proposal.has_enough_approvals() @ WalletError::InvalidOperation,
+        constraint = *multisig.to_account_info().key == proposal.multisig @ WalletError::MultisigMismatch
+    )]
+    pub proposal: Account<'info, TransactionProposal>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    // Guardian gửi lệnh thực thi, phải có quyền thực thi
+    #[account(
+        mut,
+        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &executor_guardian_id.to_le_bytes()],
+        bump = executor_guardian.bump,
+        constraint = executor_guardian.is_active @ WalletError::InactiveGuardian,
+        constraint = executor_guardian.has_permission(Guardian::PERMISSION_EXECUTE) @ WalletError::MissingPermission
+    )]
+    pub executor_guardian: Account<'info, Guardian>,
+
+    pub guardian_signer: Option<Signer<'info>>,
+
+    /// CHECK: Sysvar instructions, được kiểm tra qua address
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instruction_sysvar: AccountInfo<'info>,
+
+    /// CHECK: Được so khớp với đích đến trong hành động của đề xuất.
+    /// Chỉ cần khi đề xuất có lệnh chuyển, các đích khác truyền qua remaining_accounts
+    #[account(mut)]
+    pub destination: Option<AccountInfo<'info>>,
+
+    // Chỉ cần khi hành động là TransferToken
+    #[account(mut)]
+    pub from_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Được so khớp với token account nhận trong đề xuất (hoặc ATA của đích đến)
+    #[account(mut)]
+    pub to_token_account: Option<AccountInfo<'info>>,
+
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    // Chỉ cần khi phải tạo ATA cho đích đến
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+
+    // Chỉ cần khi ví giới hạn địa chỉ đích
+    #[account(
+        seeds = [b"address_book".as_ref(), multisig.key().as_ref()],
+        bump = address_book.bump
+    )]
+    pub address_book: Option<Account<'info, AddressBook>>,
+
+    pub clock: Sysvar<'info, Clock>,
+    pub system_program: Program<'info, System>,
+}
+
+// Giữ lại cho client cũ: cùng dispatcher với execute_proposal nhưng bắt buộc tài khoản token
+#[derive(Accounts)]
+#[instruction(proposal_id: u64, executor_guardian_id: u64)]
+pub struct ExecuteTokenProposal<'info> {
+    #[account(mut)]
+    pub multisig: Account<'info, MultiSigWallet>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"proposal".as_ref(),
+            multisig.key().as_ref(),
+            &proposal_id.to_le_bytes()
+        ],
+        bump = proposal.bump,
+        constraint = proposal.status == ProposalStatus::Pending @ WalletError::InvalidOperation,
+        constraint = proposal.has_enough_approvals() @ WalletError::InvalidOperation,
+        constraint = *multisig.to_account_info().key == proposal.multisig @ WalletError::MultisigMismatch,
+        constraint = matches!(
+            proposal.actions.as_slice(),
+            [ProposalAction::TransferToken { .. }]
+        ) @ WalletError::UnsupportedAction
+    )]
+    pub proposal: Account<'info, TransactionProposal>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    // Guardian gửi lệnh thực thi, phải có quyền thực thi
+    #[account(
+        mut,
+        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &executor_guardian_id.to_le_bytes()],
+        bump = executor_guardian.bump,
+        constraint = executor_guardian.is_active @ WalletError::InactiveGuardian,
+        constraint = executor_guardian.has_permission(Guardian::PERMISSION_EXECUTE) @ WalletError::MissingPermission
+    )]
+    pub executor_guardian: Account<'info, Guardian>,
+
+    pub guardian_signer: Option<Signer<'info>>,
+
+    /// CHECK: Sysvar instructions, được kiểm tra qua address
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instruction_sysvar: AccountInfo<'info>,
+
+    /// CHECK: Được so khớp với đích đến trong hành động của đề xuất
+    #[account(mut)]
+    pub destination: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub from_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: Được so khớp với token account nhận trong đề xuất (hoặc ATA của đích đến)
+    #[account(mut)]
+    pub to_token_account: AccountInfo<'info>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+
+    // Chỉ cần khi ví giới hạn địa chỉ đích
+    #[account(
+        seeds = [b"address_book".as_ref(), multisig.key().as_ref()],
+        bump = address_book.bump
+    )]
+    pub address_book: Option<Account<'info, AddressBook>>,
+
+    pub clock: Sysvar<'info, Clock>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Các tài khoản mà một hành động có thể cần khi thực thi
+pub struct ActionAccounts<'a, 'info> {
+    pub multisig: &'a mut Account<'info, MultiSigWallet>,
+    pub payer: AccountInfo<'info>,
+    pub destination: Option<&'a AccountInfo<'info>>,
+    pub from_token_account: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+    pub to_token_account: Option<&'a AccountInfo<'info>>,
+    pub mint: Option<&'a InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<&'a Interface<'info, TokenInterface>>,
+    pub associated_token_program: Option<&'a Program<'info, AssociatedToken>>,
+    pub system_program: AccountInfo<'info>,
+    pub remaining_accounts: &'info [AccountInfo<'info>],
+}
+
+// remaining_accounts (Invoke): với mỗi instruction, tài khoản chương trình rồi đến các account meta theo đúng thứ tự
+// remaining_accounts (AddGuardian/RemoveGuardian): tài khoản PDA của guardian và GuardianRegistry của ví
+// remaining_accounts (ChangeGuardianWeight): tài khoản PDA của guardian
+// remaining_accounts (TransferSol/TransferToken): tài khoản của các lệnh chuyển không khớp
+// tài khoản được đặt tên, cùng các tài khoản phụ của transfer hook nếu mint có
+/// Thực thi tối đa `max_actions` hành động tiếp theo (None = tất cả). Đề xuất ở trạng thái
+/// Executing cho đến khi hành động cuối cùng được thực thi.
+pub fn execute_proposal<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ExecuteProposal<'info>>,
+    proposal_id: u64,
+    max_actions: Option<u8>,
+    assertion: Option<WebAuthnAssertion>
+) -> Result<()> {
+    let clock = (*ctx.accounts.clock).clone();
+
+    check_executable(&ctx.accounts.multisig, &ctx.accounts.proposal, &clock)?;
+    verify_executor(
+        &mut ctx.accounts.executor_guardian,
+        &ctx.accounts.multisig,
+        &ctx.accounts.proposal,
+        &ctx.accounts.instruction_sysvar,
+        ctx.accounts.guardian_signer.as_ref().map(|signer| signer.key()),
+        assertion.as_ref()
+    )?;
+
+    let total = ctx.accounts.proposal.actions.len();
+    let start = ctx.accounts.proposal.executed_actions as usize;
+    let end = match max_actions {
+        Some(max_actions) => total.min(start + max_actions.max(1) as usize),
+        None => total,
+    };
+
+    debug_msg!("Thực thi đề xuất với ID: {}, hành động {}..{}/{}", proposal_id, start, end, total);
+    debug_msg!("Địa chỉ multisig: {}", ctx.accounts.multisig.key());
+    debug_msg!("Số chữ ký hiện tại: {}/{}", ctx.accounts.proposal.signatures_count, ctx.accounts.proposal.required_signatures);
+    debug_msg!("Trọng số phê duyệt hiện tại: {}/{}", ctx.accounts.proposal.approval_weight, ctx.accounts.proposal.required_weight);
+
+    for index in start..end {
+        let action = ctx.accounts.proposal.actions[index].clone();
+
+        check_destination_policy(
+            &ctx.accounts.multisig,
+            &action,
+            ctx.accounts.proposal.approvals(),
+            ctx.accounts.address_book.as_ref()
+        )?;
+
+        let transfer = resolve_transfer_accounts(ctx.accounts, ctx.remaining_accounts, &action)?;
+
+        execute_action(
+            &action,
+            &mut ActionAccounts {
+                multisig: &mut ctx.accounts.multisig,
+                payer: ctx.accounts.payer.to_account_info(),
+                destination: transfer.destination.as_ref(),
+                from_token_account: transfer.from_token_account.as_ref(),
+                to_token_account: transfer.to_token_account.as_ref(),
+                mint: transfer.mint.as_ref(),
+                token_program: transfer.token_program.as_ref(),
+                associated_token_program: ctx.accounts.associated_token_program.as_ref(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                remaining_accounts: ctx.remaining_accounts,
+            }
+        )?;
+
+        ctx.accounts.proposal.executed_actions += 1;
+    }
+
+    if end == total {
+        finish_execution(&mut ctx.accounts.multisig, &mut ctx.accounts.proposal, &clock);
+        debug_msg!("Đã thực thi đề xuất {} thành công", proposal_id);
+    } else {
+        ctx.accounts.proposal.status = ProposalStatus::Executing;
+        debug_msg!("Đề xuất {} còn {} hành động chưa thực thi", proposal_id, total - end);
+    }
+
+    emit!(ProposalExecuted {
+        multisig: ctx.accounts.multisig.key(),
+        proposal_id,
+        executed_actions: ctx.accounts.proposal.executed_actions,
+        total_actions: total as u8,
+        completed: end == total,
+    });
+
+    Ok(())
+}
+
+/// Tài khoản dùng cho một lệnh chuyển trong đề xuất nhiều hành động
+struct TransferAccounts<'info> {
+    destination: Option<AccountInfo<'info>>,
+    from_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    to_token_account: Option<AccountInfo<'info>>,
+    mint: Option<InterfaceAccount<'info, Mint>>,
+    token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+/// Lấy tài khoản được đặt tên nếu đúng địa chỉ, nếu không thì tìm trong remaining_accounts
+fn find_account<'info>(
+    named: Option<&AccountInfo<'info>>,
+    remaining_accounts: &'info [AccountInfo<'info>],
+    key: &Pubkey
+) -> Option<AccountInfo<'info>> {
+    named
+        .filter(|info| info.key == key)
+        .cloned()
+        .or_else(|| remaining_accounts.iter().find(|info| info.key == key).cloned())
+}
+
+fn resolve_transfer_accounts<'info>(
+    accounts: &ExecuteProposal<'info>,
+    remaining_accounts: &'info [AccountInfo<'info>],
+    action: &ProposalAction
+) -> Result<TransferAccounts<'info>> {
+    let mut transfer = TransferAccounts {
+        destination: accounts.destination.clone(),
+        from_token_account: accounts.from_token_account.clone(),
+        to_token_account: accounts.to_token_account.clone(),
+        mint: accounts.token_mint.clone(),
+        token_program: accounts.token_program.clone(),
+    };
+
+    if let Some(destination) = action.transfer_destination() {
+        if let Some(info) = find_account(accounts.destination.as_ref(), remaining_accounts, &destination) {
+            transfer.destination = Some(info);
+        }
+    }
+
+    let ProposalAction::TransferToken { mint, destination, recipient_token_account, .. } = action else {
+        return Ok(transfer);
+    };
+
+    if transfer.mint.as_ref().map(|account| account.key()) != Some(*mint) {
+        transfer.mint = remaining_accounts
+            .iter()
+            .find(|info| info.key == mint)
+            .map(InterfaceAccount::try_from)
+            .transpose()?;
+    }
+    let Some(mint_account) = transfer.mint.as_ref() else {
+        return Ok(transfer);
+    };
+
+    let token_program_id = *mint_account.to_account_info().owner;
+    if transfer.token_program.as_ref().map(|program| program.key()) != Some(token_program_id) {
+        transfer.token_program = remaining_accounts
+            .iter()
+            .find(|info| *info.key == token_program_id)
+            .map(Interface::try_from)
+            .transpose()?;
+    }
+
+    if transfer.from_token_account.as_ref().map(|account| account.mint) != Some(*mint) {
+        let source = get_associated_token_address_with_program_id(
+            &accounts.multisig.key(),
+            mint,
+            &token_program_id
+        );
+        transfer.from_token_account = remaining_accounts
+            .iter()
+            .find(|info| *info.key == source)
+            .map(InterfaceAccount::try_from)
+            .transpose()?;
+    }
+
+    let recipient = recipient_token_account.unwrap_or_else(|| {
+        get_associated_token_address_with_program_id(destination, mint, &token_program_id)
+    });
+    transfer.to_token_account = find_account(
+        accounts.to_token_account.as_ref(),
+        remaining_accounts,
+        &recipient
+    );
+
+    Ok(transfer)
+}
+
+pub fn execute_token_proposal<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ExecuteTokenProposal<'info>>,
+    proposal_id: u64,
+    assertion: Option<WebAuthnAssertion>
+) -> Result<()> {
+    let clock = &ctx.accounts.clock;
+    let action = ctx.accounts.proposal.actions[0].clone();
+
+    check_executable(&ctx.accounts.multisig, &ctx.accounts.proposal, clock)?;
+    verify_executor(
+        &mut ctx.accounts.executor_guardian,
+        &ctx.accounts.multisig,
+        &ctx.accounts.proposal,
+        &ctx.accounts.instruction_sysvar,
+        ctx.accounts.guardian_signer.as_ref().map(|signer| signer.key()),
+        assertion.as_ref()
+    )?;
+    check_destination_policy(
+        &ctx.accounts.multisig,
+        &action,
+        ctx.accounts.proposal.approvals(),
+        ctx.accounts.address_book.as_ref()
+    )?;
+
+    debug_msg!("Thực thi đề xuất chuyển token với ID: {}", proposal_id);
+    debug_msg!("Địa chỉ multisig: {}", ctx.accounts.multisig.key());
+    debug_msg!("Số chữ ký hiện tại: {}/{}", ctx.accounts.proposal.signatures_count, ctx.accounts.proposal.required_signatures);
+    debug_msg!("Trọng số phê duyệt hiện tại: {}/{}", ctx.accounts.proposal.approval_weight, ctx.accounts.proposal.required_weight);
+
+    execute_action(
+        &action,
+        &mut ActionAccounts {
+            multisig: &mut ctx.accounts.multisig,
+            payer: ctx.accounts.payer.to_account_info(),
+            destination: Some(&ctx.accounts.destination),
+            from_token_account: Some(&ctx.accounts.from_token_account),
+            to_token_account: Some(&ctx.accounts.to_token_account),
+            mint: Some(&ctx.accounts.token_mint),
+            token_program: Some(&ctx.accounts.token_program),
+            associated_token_program: ctx.accounts.associated_token_program.as_ref(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+        }
+    )?;
+
+    ctx.accounts.proposal.executed_actions = 1;
+    finish_execution(&mut ctx.accounts.multisig, &mut ctx.accounts.proposal, clock);
+
+    emit!(ProposalExecuted {
+        multisig: ctx.accounts.multisig.key(),
+        proposal_id,
+        executed_actions: 1,
+        total_actions: 1,
+        completed: true,
+    });
+
+    debug_msg!("Đã thực thi đề xuất {} thành công", proposal_id);
+
+    Ok(())
+}
+
+fn check_executable(
+    multisig: &Account<MultiSigWallet>,
+    proposal: &TransactionProposal,
+    clock: &Clock
+) -> Result<()> {
+    require!(
+        *multisig.to_account_info().owner == crate::ID,
+        WalletError::InvalidOwner
+    );
+
+    require!(
+        proposal.has_enough_approvals(),
+        WalletError::InvalidOperation
+    );
+
+    // Đề xuất đã bắt đầu thực thi phải được chạy hết dù đã quá hạn
+    require!(
+        proposal.status == ProposalStatus::Executing || !proposal.is_expired(clock.unix_timestamp),
+        WalletError::ProposalExpired
+    );
+
+    Ok(())
+}
+
+fn verify_executor(
+    executor_guardian: &mut Guardian,
+    multisig: &Account<MultiSigWallet>,
+    proposal: &Account<TransactionProposal>,
+    instruction_sysvar: &AccountInfo,
+    signer: Option<Pubkey>,
+    assertion: Option<&WebAuthnAssertion>
+) -> Result<()> {
+    verify_guardian_auth(
+        executor_guardian,
+        multisig,
+        instruction_sysvar,
+        signer,
+        assertion,
+        &format!("execute:proposal_{},contents_{}", proposal.proposal_id, to_hex(&proposal.contents_hash)),
+        proposal.key()
+    )
+}
+
+/// Chặn (hoặc đòi thêm chữ ký cho) lệnh chuyển tới địa chỉ ngoài sổ địa chỉ.
+/// Invoke có thể chuyển tài sản tới bất kỳ đâu nên luôn bị xem như địa chỉ ngoài sổ.
+pub fn check_destination_policy(
+    multisig: &MultiSigWallet,
+    action: &ProposalAction,
+    approvals: u16,
+    address_book: Option<&Account<AddressBook>>
+) -> Result<()> {
+    if let Some(destination) = action.transfer_destination() {
+        let listed = address_book.is_some_and(|book| book.contains(&destination));
+        multisig.destination_policy.check(listed, approvals)?;
+    } else if matches!(action, ProposalAction::Invoke { .. }) {
+        multisig.destination_policy.check(false, approvals)?;
+    }
+
+    Ok(())
+}
+
+fn finish_execution(
+    multisig: &mut MultiSigWallet,
+    proposal: &mut TransactionProposal,
+    clock: &Clock
+) {
+    proposal.status = ProposalStatus::Executed;
+    proposal.executed_at = Some(clock.unix_timestamp);
+
+    multisig.transaction_nonce += 1;
+    multisig.last_transaction_timestamp = clock.unix_timestamp;
+}
+
+/// Dispatcher duy nhất cho mọi ProposalAction
+pub fn execute_action(action: &ProposalAction, accounts: &mut ActionAccounts) -> Result<()> {
+    match action {
+        ProposalAction::TransferSol { amount, destination } => {
+            transfer_sol(accounts, *amount, *destination)
+        },
+        ProposalAction::TransferToken { mint, amount, destination, recipient_token_account } => {
+            transfer_token(accounts, *mint, *amount, *destination, *recipient_token_account)
+        },
+        ProposalAction::Invoke { instructions } => {
+            invoke_instructions(accounts, instructions)
+        },
+        ProposalAction::ChangeThreshold { threshold, rejection_threshold } => {
+            change_threshold(accounts, *threshold, *rejection_threshold)
+        },
+        ProposalAction::ChangeWeightThreshold { weight_threshold } => {
+            accounts.multisig.validate_weight_threshold(*weight_threshold)?;
+            accounts.multisig.weight_threshold = *weight_threshold;
+            debug_msg!("Đã đổi ngưỡng trọng số thành {}", weight_threshold);
+            Ok(())
+        },
+        ProposalAction::AddGuardian {
+            guardian_id,
+            name,
+            recovery_hash_intermediate,
+            is_owner,
+            permissions,
+            weight,
+            auth
+        } => {
+            add_guardian(
+                accounts,
+                *guardian_id,
+                name.clone(),
+                *recovery_hash_intermediate,
+                *is_owner,
+                *permissions,
+                Guardian::resolve_weight(*weight)?,
+                *auth
+            )
+        },
+        ProposalAction::RemoveGuardian { guardian_id } => {
+            remove_guardian(accounts, *guardian_id)
+        },
+        ProposalAction::ChangeRecoveryDelay { recovery_delay } => {
+            accounts.multisig.recovery_delay = *recovery_delay;
+            debug_msg!("Đã đổi thời gian chờ khôi phục thành {} giây", recovery_delay);
+            Ok(())
+        },
+        ProposalAction::ChangeRecoveryThreshold { recovery_threshold } => {
+            accounts.multisig.validate_threshold(*recovery_threshold)?;
+            accounts.multisig.recovery_threshold = *recovery_threshold;
+            debug_msg!("Đã đổi số guardian cần phê duyệt khôi phục thành {}", recovery_threshold);
+            Ok(())
+        },
+        ProposalAction::ChangeMaxGuardians { max_guardians } => {
+            accounts.multisig.validate_max_guardians(*max_guardians)?;
+            accounts.multisig.max_guardians = *max_guardians;
+            debug_msg!("Đã đổi số guardian tối đa thành {}", max_guardians);
+            Ok(())
+        },
+        ProposalAction::SetSpendingLimit { mint, amount, period, destinations } => {
+            set_spending_limit(accounts, *mint, *amount, *period, destinations.clone())
+        },
+        ProposalAction::RemoveSpendingLimit { mint } => {
+            remove_spending_limit(accounts, *mint)
+        },
+        ProposalAction::AddAddressBookEntry { address, label } => {
+            add_address_book_entry(accounts, *address, label.clone())
+        },
+        ProposalAction::RemoveAddressBookEntry { address } => {
+            remove_address_book_entry(accounts, *address)
+        },
+        ProposalAction::SetDestinationPolicy { policy } => {
+            accounts.multisig.destination_policy = *policy;
+            debug_msg!("Đã đổi chính sách địa chỉ đích");
+            Ok(())
+        },
+        ProposalAction::ChangeGuardianWeight { guardian_id, weight } => {
+            change_guardian_weight(accounts, *guardian_id, *weight)
+        },
+    }
+}
+
+fn change_threshold(accounts: &mut ActionAccounts, threshold: u8, rejection_threshold: u8) -> Result<()> {
+    let multisig = &mut *accounts.multisig;
+
+    multisig.validate_threshold(threshold)?;
+    require!(
+        rejection_threshold <= multisig.active_guardian_count,
+        WalletError::InvalidThreshold
+    );
+
+    multisig.threshold = threshold;
+    multisig.rejection_threshold = rejection_threshold;
+
+    debug_msg!("Đã đổi ngưỡng thành {}, ngưỡng từ chối: {}", threshold, multisig.rejection_quorum());
+
+    Ok(())
+}
+
+/// Tìm tài khoản PDA có `seeds` trong remaining_accounts
+fn find_pda_account<'info>(
+    accounts: &ActionAccounts<'_, 'info>,
+    seeds: &[&[u8]],
+    error: WalletError
+) -> Result<(&'info AccountInfo<'info>, u8)> {
+    let (key, bump) = Pubkey::find_program_address(seeds, &crate::ID);
+
+    let info = accounts.remaining_accounts
+        .iter()
+        .find(|info| info.key() == key)
+        .filter(|info| info.is_writable)
+        .ok_or(error)?;
+
+    Ok((info, bump))
+}
+
+fn find_guardian_account<'info>(
+    accounts: &ActionAccounts<'_, 'info>,
+    guardian_id: u64
+) -> Result<(&'info AccountInfo<'info>, u8)> {
+    find_pda_account(
+        accounts,
+        &[b"guardian".as_ref(), accounts.multisig.key().as_ref(), &guardian_id.to_le_bytes()],
+        WalletError::InvalidGuardian
+    )
+}
+
+/// Tạo tài khoản PDA của chương trình, payer trả rent
+/// Tạo PDA giống `init` của Anchor: địa chỉ PDA đoán trước được nên có thể đã bị
+/// gửi sẵn lamports, khi đó chỉ nạp thêm cho đủ rent rồi allocate và assign.
+fn create_pda_account<'info>(
+    accounts: &ActionAccounts<'_, 'info>,
+    info: &AccountInfo<'info>,
+    signer_seeds: &[&[u8]],
+    space: usize
+) -> Result<()> {
+    let rent = Rent::get()?.minimum_balance(space);
+    let current_lamports = info.lamports();
+
+    if current_lamports == 0 {
+        return system_program::create_account(
+            CpiContext::new_with_signer(
+                accounts.system_program.clone(),
+                CreateAccount {
+                    from: accounts.payer.clone(),
+                    to: info.clone(),
+                },
+                &[signer_seeds],
+            ),
+            rent,
+            space as u64,
+            &crate::ID
+        );
+    }
+
+    let top_up = rent.saturating_sub(current_lamports);
+    if top_up > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                accounts.system_program.clone(),
+                Transfer {
+                    from: accounts.payer.clone(),
+                    to: info.clone(),
+                },
+            ),
+            top_up
+        )?;
+    }
+
+    system_program::allocate(
+        CpiContext::new_with_signer(
+            accounts.system_program.clone(),
+            Allocate {
+                account_to_allocate: info.clone(),
+            },
+            &[signer_seeds],
+        ),
+        space as u64
+    )?;
+
+    system_program::assign(
+        CpiContext::new_with_signer(
+            accounts.system_program.clone(),
+            Assign {
+                account_to_assign: info.clone(),
+            },
+            &[signer_seeds],
+        ),
+        &crate::ID
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn add_guardian(
+    accounts: &mut ActionAccounts,
+    guardian_id: u64,
+    name: String,
+    recovery_hash_intermediate: [u8; 32],
+    is_owner: bool,
+    permissions: u8,
+    weight: u8,
+    auth: GuardianAuth
+) -> Result<()> {
+    let (guardian_info, bump) = find_guardian_account(accounts, guardian_id)?;
+    let multisig_key = accounts.multisig.key();
+    let guardian_id_bytes = guardian_id.to_le_bytes();
+    let seeds = &[
+        b"guardian".as_ref(),
+        multisig_key.as_ref(),
+        &guardian_id_bytes,
+        &[bump]
+    ];
+
+    create_pda_account(accounts, guardian_info, seeds, Guardian::SPACE)?;
+
+    let guardian = Guardian {
+        wallet: multisig_key,
+        guardian_id,
+        name,
+        is_active: true,
+        recovery_hash: hash(&recovery_hash_intermediate).to_bytes(),
+        is_owner,
+        permissions,
+        weight,
+        auth,
+        sign_count: 0,
+        nonce: 0,
+        bump,
+    };
+    guardian.try_serialize(&mut &mut guardian_info.try_borrow_mut_data()?[..])?;
+
+    accounts.multisig.on_guardian_added(is_owner, weight)?;
+
+    let mut registry = find_guardian_registry(accounts)?;
+    registry.guardian_ids.push(guardian_id);
+    resize_guardian_registry(accounts, &registry)?;
+    registry.exit(&crate::ID)?;
+
+    emit!(GuardianAdded {
+        multisig: multisig_key,
+        guardian_id,
+        is_owner,
+    });
+
+    debug_msg!("Đã thêm guardian {} qua đề xuất", guardian_id);
+
+    Ok(())
+}
+
+fn change_guardian_weight(accounts: &mut ActionAccounts, guardian_id: u64, weight: u8) -> Result<()> {
+    let weight = Guardian::resolve_weight(Some(weight))?;
+    let (guardian_info, _) = find_guardian_account(accounts, guardian_id)?;
+    let mut guardian = Account::<Guardian>::try_from(guardian_info)?;
+
+    require!(
+        guardian.wallet == accounts.multisig.key() && guardian.guardian_id == guardian_id,
+        WalletError::InvalidGuardian
+    );
+
+    accounts.multisig.on_guardian_weight_changed(&guardian, weight)?;
+    guardian.weight = weight;
+    guardian.exit(&crate::ID)?;
+
+    emit!(GuardianWeightChanged {
+        multisig: accounts.multisig.key(),
+        guardian_id,
+        weight,
+    });
+
+    debug_msg!("Đã đổi trọng số guardian {} thành {}", guardian_id, weight);
+
+    Ok(())
+}
+
+fn remove_guardian(accounts: &mut ActionAccounts, guardian_id: u64) -> Result<()> {
+    let (guardian_info, _) = find_guardian_account(accounts, guardian_id)?;
+    let guardian = Account::<Guardian>::try_from(guardian_info)?;
+
+    require!(
+        guardian.wallet == accounts.multisig.key() && guardian.guardian_id == guardian_id,
+        WalletError::InvalidGuardian
+    );
+
+    accounts.multisig.on_guardian_removed(&guardian)?;
+    guardian.close(accounts.payer.clone())?;
+
+    let mut registry = find_guardian_registry(accounts)?;
+    registry.remove(guardian_id);
+    resize_guardian_registry(accounts, &registry)?;
+    registry.exit(&crate::ID)?;
+
+    emit!(GuardianRemoved {
+        multisig: accounts.multisig.key(),
+        guardian_id,
+    });
+
+    debug_msg!("Đã xoá guardian {} qua đề xuất", guardian_id);
+
+    Ok(())
+}
+
+fn find_guardian_registry<'info>(
+    accounts: &ActionAccounts<'_, 'info>
+) -> Result<Account<'info, GuardianRegistry>> {
+    let (registry_info, _) = find_pda_account(
+        accounts,
+        &[b"guardian_registry".as_ref(), accounts.multisig.key().as_ref()],
+        WalletError::InvalidProposalAccount
+    )?;
+
+    Account::<GuardianRegistry>::try_from(registry_info)
+}
+
+/// Đổi kích thước registry theo số guardian hiện có, payer bù thêm hoặc nhận lại rent chênh lệch
+fn resize_guardian_registry<'info>(
+    accounts: &ActionAccounts<'_, 'info>,
+    registry: &Account<'info, GuardianRegistry>
+) -> Result<()> {
+    let registry_info = registry.to_account_info();
+    let space = GuardianRegistry::space(registry.guardian_ids.len());
+    let rent = Rent::get()?.minimum_balance(space);
+    let lamports = registry_info.lamports();
+
+    if rent > lamports {
+        system_program::transfer(
+            CpiContext::new(
+                accounts.system_program.clone(),
+                Transfer {
+                    from: accounts.payer.clone(),
+                    to: registry_info.clone(),
+                },
+            ),
+            rent - lamports
+        )?;
+    } else if lamports > rent {
+        **registry_info.try_borrow_mut_lamports()? -= lamports - rent;
+        **accounts.payer.try_borrow_mut_lamports()? += lamports - rent;
+    }
+
+    registry_info.resize(space)?;
+
+    Ok(())
+}
+
+fn invoke_instructions(accounts: &ActionAccounts, instructions: &[ProposalInstruction]) -> Result<()> {
+    let multisig = &*accounts.multisig;
+    let multisig_key = multisig.key();
+    let credential_id_seed = multisig_seed(&multisig.credential_id);
+    let seeds = &[
+        b"multisig".as_ref(),
+        &[MULTISIG_SEED_VERSION],
+        &credential_id_seed,
+        &[multisig.bump]
+    ];
+    let (vault_key, vault_bump) = Pubkey::find_program_address(
+        &[b"vault".as_ref(), multisig_key.as_ref()],
+        &crate::ID
+    );
+    let vault_seeds = &[
+        b"vault".as_ref(),
+        multisig_key.as_ref(),
+        &[vault_bump]
+    ];
+    let signer_seeds = &[&seeds[..], &vault_seeds[..]];
+
+    let mut remaining = accounts.remaining_accounts.iter();
+
+    for ix in instructions {
+        let program_info = remaining.next().ok_or(WalletError::InvalidInstructionData)?;
+        require!(
+            program_info.key() == ix.program_id,
+            WalletError::InvalidInstructionData
+        );
+
+        let mut account_infos = Vec::with_capacity(ix.accounts.len() + 1);
+        for meta in &ix.accounts {
+            let info = remaining.next().ok_or(WalletError::InvalidInstructionData)?;
+            require!(info.key() == meta.pubkey, WalletError::InvalidInstructionData);
+            require!(!meta.is_writable || info.is_writable, WalletError::InvalidInstructionData);
+            // Ví multisig và vault ký qua invoke_signed, các signer khác phải ký transaction
+            require!(
+                !meta.is_signer || info.is_signer || meta.pubkey == multisig_key || meta.pubkey == vault_key,
+                WalletError::InvalidInstructionData
+            );
+            account_infos.push(info.clone());
+        }
+        account_infos.push(program_info.clone());
+
+        let instruction = Instruction {
+            program_id: ix.program_id,
+            accounts: ix.accounts
+                .iter()
+                .map(|meta| AccountMeta {
+                    pubkey: meta.pubkey,
+                    is_signer: meta.is_signer,
+                    is_writable: meta.is_writable,
+                })
+                .collect(),
+            data: ix.data.clone(),
+        };
+
+        invoke_signed(&instruction, &account_infos, signer_seeds)?;
+
+        debug_msg!("Đã gọi chương trình {}", ix.program_id);
+    }
+
+    require!(remaining.next().is_none(), WalletError::InvalidInstructionData);
+
+    Ok(())
+}
+
+fn find_spending_limit_account<'info>(
+    accounts: &ActionAccounts<'_, 'info>,
+    mint: Pubkey
+) -> Result<(&'info AccountInfo<'info>, u8)> {
+    find_pda_account(
+        accounts,
+        &[b"spending_limit".as_ref(), accounts.multisig.key().as_ref(), mint.as_ref()],
+        WalletError::InvalidProposalAccount
+    )
+}
+
+fn set_spending_limit(
+    accounts: &mut ActionAccounts,
+    mint: Pubkey,
+    amount: u64,
+    period: i64,
+    destinations: Vec<Pubkey>
+) -> Result<()> {
+    let (limit_info, bump) = find_spending_limit_account(accounts, mint)?;
+    let multisig_key = accounts.multisig.key();
+
+    if limit_info.data_is_empty() {
+        let seeds = &[
+            b"spending_limit".as_ref(),
+            multisig_key.as_ref(),
+            mint.as_ref(),
+            &[bump]
+        ];
+        create_pda_account(accounts, limit_info, seeds, SpendingLimit::SPACE)?;
+
+        let limit = SpendingLimit {
+            multisig: multisig_key,
+            mint,
+            amount,
+            period,
+            spends: Vec::new(),
+            destinations,
+            bump,
+        };
+        limit.try_serialize(&mut &mut limit_info.try_borrow_mut_data()?[..])?;
+    } else {
+        // Các khoản đã chi vẫn tính vào cửa sổ hiện tại
+        let mut limit = Account::<SpendingLimit>::try_from(limit_info)?;
+        limit.amount = amount;
+        limit.period = period;
+        limit.destinations = destinations;
+        limit.exit(&crate::ID)?;
+    }
+
+    debug_msg!("Đã đặt hạn mức chi tiêu ({}): {} mỗi {} giây", mint, amount, period);
+
+    Ok(())
+}
+
+fn remove_spending_limit(accounts: &mut ActionAccounts, mint: Pubkey) -> Result<()> {
+    let (limit_info, _) = find_spending_limit_account(accounts, mint)?;
+    let limit = Account::<SpendingLimit>::try_from(limit_info)?;
+
+    limit.close(accounts.payer.clone())?;
+
+    debug_msg!("Đã xoá hạn mức chi tiêu ({})", mint);
+
+    Ok(())
+}
+
+fn find_address_book_account<'info>(
+    accounts: &ActionAccounts<'_, 'info>
+) -> Result<(&'info AccountInfo<'info>, u8)> {
+    find_pda_account(
+        accounts,
+        &[b"address_book".as_ref(), accounts.multisig.key().as_ref()],
+        WalletError::InvalidProposalAccount
+    )
+}
+
+fn add_address_book_entry(accounts: &mut ActionAccounts, address: Pubkey, label: String) -> Result<()> {
+    let (book_info, bump) = find_address_book_account(accounts)?;
+    let multisig_key = accounts.multisig.key();
+
+    if book_info.data_is_empty() {
+        let seeds = &[
+            b"address_book".as_ref(),
+            multisig_key.as_ref(),
+            &[bump]
+        ];
+        create_pda_account(accounts, book_info, seeds, AddressBook::SPACE)?;
+
+        let book = AddressBook {
+            multisig: multisig_key,
+            entries: Vec::new(),
+            bump,
+        };
+        book.try_serialize(&mut &mut book_info.try_borrow_mut_data()?[..])?;
+    }
+
+    let mut book = Account::<AddressBook>::try_from(book_info)?;
+    require!(!book.contains(&address), WalletError::InvalidOperation);
+    require!(
+        book.entries.len() < MAX_ADDRESS_BOOK_ENTRIES,
+        WalletError::LimitExceeded
+    );
+
+    debug_msg!("Đã thêm {} ({}) vào sổ địa chỉ", address, label);
+
+    book.entries.push(AddressBookEntry { address, label });
+    book.exit(&crate::ID)?;
+
+    Ok(())
+}
+
+fn remove_address_book_entry(accounts: &mut ActionAccounts, address: Pubkey) -> Result<()> {
+    let (book_info, _) = find_address_book_account(accounts)?;
+    let mut book = Account::<AddressBook>::try_from(book_info)?;
+
+    let len_before = book.entries.len();
+    book.entries.retain(|entry| entry.address != address);
+    require!(book.entries.len() < len_before, WalletError::InvalidOperation);
+    book.exit(&crate::ID)?;
+
+    debug_msg!("Đã xoá {} khỏi sổ địa chỉ", address);
+
+    Ok(())
+}
+
+fn transfer_sol(accounts: &ActionAccounts, amount: u64, destination: Pubkey) -> Result<()> {
+    let destination_info = accounts.destination.ok_or(WalletError::InvalidOperation)?;
+    require!(
+        destination_info.key() == destination,
+        WalletError::InvalidOperation
+    );
+
+    let multisig_info = accounts.multisig.to_account_info();
+
+    let dest_starting_lamports = destination_info.lamports();
+    **destination_info.lamports.borrow_mut() = dest_starting_lamports
+        .checked_add(amount)
+        .ok_or(WalletError::ArithmeticOverflow)?;
+
+    let multisig_starting_lamports = multisig_info.lamports();
+    **multisig_info.lamports.borrow_mut() = multisig_starting_lamports
+        .checked_sub(amount)
+        .ok_or(WalletError::InsufficientFunds)?;
+
+    debug_msg!("Đã chuyển {} SOL đến {}", amount as f64 / 1_000_000_000.0, destination);
+
+    Ok(())
+}
+
+fn transfer_token(
+    accounts: &ActionAccounts,
+    mint: Pubkey,
+    amount: u64,
+    destination: Pubkey,
+    recipient_token_account: Option<Pubkey>
+) -> Result<()> {
+    let from_token_account = accounts.from_token_account.ok_or(WalletError::InvalidOperation)?;
+    let to_token_account = accounts.to_token_account.ok_or(WalletError::InvalidOperation)?;
+    let mint_account = accounts.mint.ok_or(WalletError::InvalidOperation)?;
+    let token_program = accounts.token_program.ok_or(WalletError::InvalidOperation)?;
+    let destination_info = accounts.destination.ok_or(WalletError::InvalidOperation)?;
+
+    require!(
+        destination_info.key() == destination,
+        WalletError::InvalidOperation
+    );
+
+    require!(
+        mint_account.key() == mint,
+        WalletError::InvalidOperation
+    );
+
+    require!(
+        from_token_account.mint == mint,
+        WalletError::InvalidOperation
+    );
+
+    require!(
+        from_token_account.owner == accounts.multisig.key(),
+        WalletError::InvalidOwner
+    );
+
+    // Token account nhận phải đúng như đề xuất đã ghim, hoặc là ATA của đích đến
+    match recipient_token_account {
+        Some(recipient_token_account) => {
+            require!(
+                to_token_account.key() == recipient_token_account,
+                WalletError::InvalidRecipientTokenAccount
+            );
+        },
+        None => {
+            let ata = get_associated_token_address_with_program_id(&destination, &mint, token_program.key);
+            require!(
+                to_token_account.key() == ata,
+                WalletError::InvalidRecipientTokenAccount
+            );
+
+            if to_token_account.data_is_empty() {
+                create_recipient_ata(accounts, to_token_account, mint_account, token_program)?;
+            }
+        },
+    }
+
+    require!(
+        to_token_account.owner == token_program.key,
+        WalletError::InvalidRecipientTokenAccount
+    );
+    let recipient = TokenAccount::try_deserialize(&mut &to_token_account.try_borrow_data()?[..])?;
+    require!(
+        recipient.mint == mint && recipient.owner == destination,
+        WalletError::InvalidRecipientTokenAccount
+    );
+
+    let multisig = &*accounts.multisig;
+    let credential_id_seed = multisig_seed(&multisig.credential_id);
+    let seeds = &[
+        b"multisig".as_ref(),
+        &[MULTISIG_SEED_VERSION],
+        &credential_id_seed,
+        &[multisig.bump]
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    let fee = transfer_checked_signed(
+        token_program,
+        from_token_account.to_account_info(),
+        mint_account,
+        to_token_account.clone(),
+        multisig.to_account_info(),
+        accounts.remaining_accounts,
+        amount,
+        signer_seeds
+    )?;
+
+    debug_msg!("Đã chuyển {} token ({}) từ multisig đến {}", amount, mint, destination);
+    if fee > 0 {
+        debug_msg!("Phí chuyển: {}, người nhận thực nhận: {}", fee, amount - fee);
+    }
+
+    Ok(())
+}
+
+/// Tạo ATA cho đích đến, người thực thi trả rent
+fn create_recipient_ata<'info>(
+    accounts: &ActionAccounts<'_, 'info>,
+    to_token_account: &AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    token_program: &Interface<'info, TokenInterface>
+) -> Result<()> {
+    let associated_token_program = accounts.associated_token_program
+        .ok_or(WalletError::InvalidRecipientTokenAccount)?;
+    let destination = accounts.destination.ok_or(WalletError::InvalidOperation)?;
+
+    associated_token::create_idempotent(CpiContext::new(
+        associated_token_program.to_account_info(),
+        associated_token::Create {
+            payer: accounts.payer.clone(),
+            associated_token: to_token_account.clone(),
+            authority: destination.clone(),
+            mint: mint.to_account_info(),
+            system_program: accounts.system_program.clone(),
+            token_program: token_program.to_account_info(),
+        },
+    ))?;
+
+    debug_msg!("Đã tạo token account {} cho {}", to_token_account.key(), destination.key());
+
+    Ok(())
+}
+
+/// Phí chuyển của mint Token-2022 có extension TransferFeeConfig, None nếu không có
+fn transfer_fee_for(mint: &InterfaceAccount<Mint>, amount: u64) -> Result<Option<u64>> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner != spl_token_2022::ID {
+        return Ok(None);
+    }
+
+    let data = mint_info.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    let Ok(config) = state.get_extension::<TransferFeeConfig>() else {
+        return Ok(None);
+    };
+
+    let fee = config
+        .calculate_epoch_fee(Clock::get()?.epoch, amount)
+        .ok_or(WalletError::ArithmeticOverflow)?;
+
+    Ok(Some(fee))
+}
+
+/// transfer_checked cho cả Token và Token-2022, trả về phí chuyển đã trừ.
+/// `additional_accounts` là các tài khoản phụ của transfer hook nếu mint có.
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_checked_signed<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    from: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    additional_accounts: &[AccountInfo<'info>],
+    amount: u64,
+    signer_seeds: &[&[&[u8]]]
+) -> Result<u64> {
+    match transfer_fee_for(mint, amount)? {
+        Some(fee) => {
+            invoke_transfer_checked_with_fee(
+                token_program.key,
+                from,
+                mint.to_account_info(),
+                to,
+                authority,
+                additional_accounts,
+                amount,
+                mint.decimals,
+                fee,
+                signer_seeds
+            )?;
+            Ok(fee)
+        },
+        None => {
+            invoke_transfer_checked(
+                token_program.key,
+                from,
+                mint.to_account_info(),
+                to,
+                authority,
+                additional_accounts,
+                amount,
+                mint.decimals,
+                signer_seeds
+            )?;
+            Ok(0)
+        },
+    }
+}
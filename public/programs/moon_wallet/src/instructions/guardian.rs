@@ -1,18 +1,29 @@
 use anchor_lang::prelude::*;
 use crate::state::*;
 use crate::errors::*;
+use crate::events::*;
 use anchor_lang::solana_program::hash::hash;
 
 
-use crate::instructions::wallet::process_credential_id_seed;
+use crate::instructions::wallet::{multisig_seed, MULTISIG_SEED_VERSION};
+use crate::instructions::proposal::to_hex;
+use crate::webauthn::{verify_webauthn_assertion, WebAuthnAssertion};
 
 
 #[derive(Accounts)]
-#[instruction(guardian_id: u64)]
+#[instruction(
+    guardian_id: u64,
+    guardian_name: String,
+    recovery_hash_intermediate: [u8; 32],
+    is_owner: bool,
+    permissions: u8,
+    auth: GuardianAuth,
+    owner_guardian_id: u64
+)]
 pub struct AddGuardian<'info> {
     #[account(
         mut,
-        seeds = [b"multisig".as_ref(), &process_credential_id_seed(&multisig.credential_id)],
+        seeds = [b"multisig".as_ref(), &[MULTISIG_SEED_VERSION], &multisig_seed(&multisig.credential_id)],
         bump = multisig.bump
     )]
     pub multisig: Account<'info, MultiSigWallet>,
@@ -20,51 +31,111 @@ pub struct AddGuardian<'info> {
     #[account(
         init,
         payer = payer,
-        space = 8 + 
-               32 +  
-               8 + 
-               4 + 32 + 
-               1 +  
-               32 +  
-               1 +   
-               1 + 33 + 
-               1,    
+        space = Guardian::SPACE,
         seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &guardian_id.to_le_bytes()],
         bump
     )]
     pub guardian: Account<'info, Guardian>,
     
+    #[account(
+        mut,
+        seeds = [b"guardian_registry".as_ref(), multisig.key().as_ref()],
+        bump = guardian_registry.bump,
+        realloc = GuardianRegistry::space(guardian_registry.guardian_ids.len() + 1),
+        realloc::payer = payer,
+        realloc::zero = false
+    )]
+    pub guardian_registry: Account<'info, GuardianRegistry>,
+    
     /// CHECK: Không còn cần thiết nhưng giữ lại để tương thích
     pub guardian_pubkey: AccountInfo<'info>,
     
+    /// Guardian có quyền quản lý guardian xác nhận việc thêm, bỏ trống khi thêm owner đầu tiên
+    #[account(
+        mut,
+        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &owner_guardian_id.to_le_bytes()],
+        bump = owner_guardian.bump,
+        constraint = owner_guardian.has_permission(Guardian::PERMISSION_MANAGE_GUARDIANS) @ WalletError::MissingPermission,
+        constraint = owner_guardian.is_active @ WalletError::InactiveGuardian
+    )]
+    pub owner_guardian: Option<Account<'info, Guardian>>,
+    
+    /// Chữ ký ed25519 của guardian owner nếu guardian đó dùng GuardianAuth::Ed25519/Both
+    pub guardian_signer: Option<Signer<'info>>,
+    
     #[account(mut)]
     pub payer: Signer<'info>,
     
+    /// CHECK: Sysvar instructions, được kiểm tra qua address
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instruction_sysvar: AccountInfo<'info>,
+    
+    pub clock: Sysvar<'info, Clock>,
     pub system_program: Program<'info, System>,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn add_guardian(
     ctx: Context<AddGuardian>,
     guardian_id: u64,
     guardian_name: String,
     recovery_hash_intermediate: [u8; 32],
     is_owner: bool,
-    webauthn_pubkey: Option<[u8; 33]>,
+    permissions: u8,
+    auth: GuardianAuth,
+    _owner_guardian_id: u64,
+    assertion: Option<WebAuthnAssertion>,
 ) -> Result<()> {
     let multisig = &mut ctx.accounts.multisig;
     let guardian = &mut ctx.accounts.guardian;
     
-    require!(multisig.guardian_count < 8, WalletError::LimitExceeded);
     require!(guardian_name.len() <= 32, WalletError::NameTooLong);
     
-    
     if is_owner {
-    
+        require!(auth.webauthn_pubkey().is_some(), WalletError::WebAuthnNotConfigured);
     }
-    
-    
-    if is_owner {
-        require!(webauthn_pubkey.is_some(), WalletError::WebAuthnNotConfigured);
+    Guardian::validate_permissions(permissions, is_owner)?;
+    // Một guardian quản lý không được tự tạo guardian nặng ký; trọng số khác chỉ đặt qua đề xuất AddGuardian
+    let weight = Guardian::DEFAULT_WEIGHT;
+    
+    if multisig.guardian_count == 0 {
+        // Guardian đầu tiên phải là owner và do chính người tạo ví thêm vào
+        require!(is_owner, WalletError::InvalidOperation);
+        require!(
+            ctx.accounts.payer.key() == multisig.owner,
+            WalletError::InvalidOperation
+        );
+    } else {
+        let owner_guardian = ctx.accounts.owner_guardian
+            .as_mut()
+            .ok_or(WalletError::InvalidGuardian)?;
+        
+        // Guardian không phải owner chỉ được thêm guardian thường với quyền không vượt quá quyền của mình
+        require!(
+            owner_guardian.is_owner || (!is_owner && permissions & !owner_guardian.permissions == 0),
+            WalletError::MissingPermission
+        );
+        
+        // Owner ký toàn bộ thông số của guardian mới để assertion không bị dùng lại với auth/quyền khác
+        let params_hash = hash(&(
+            guardian_id,
+            &guardian_name,
+            recovery_hash_intermediate,
+            is_owner,
+            permissions,
+            weight,
+            auth
+        ).try_to_vec()?).to_bytes();
+        
+        verify_guardian_auth(
+            owner_guardian,
+            multisig,
+            &ctx.accounts.instruction_sysvar,
+            ctx.accounts.guardian_signer.as_ref().map(|signer| signer.key()),
+            assertion.as_ref(),
+            &format!("add_guardian:new_guardian_{},params_{}", guardian_id, to_hex(&params_hash)),
+            guardian.key()
+        )?;
     }
     
     let hash_result = hash(&recovery_hash_intermediate);
@@ -76,14 +147,25 @@ pub fn add_guardian(
     guardian.is_active = true;
     guardian.recovery_hash = final_hash; 
     guardian.is_owner = is_owner;
-    guardian.webauthn_pubkey = webauthn_pubkey;
+    guardian.permissions = permissions;
+    guardian.weight = weight;
+    guardian.auth = auth;
+    guardian.sign_count = 0;
+    guardian.nonce = 0;
     guardian.bump = ctx.bumps.guardian;
 
-    multisig.guardian_count += 1;
+    multisig.on_guardian_added(is_owner, weight)?;
+    ctx.accounts.guardian_registry.guardian_ids.push(guardian_id);
+    
+    emit!(GuardianAdded {
+        multisig: multisig.key(),
+        guardian_id,
+        is_owner,
+    });
     
-    msg!("Guardian đã được thêm thành công");
+    debug_msg!("Guardian đã được thêm thành công");
     if is_owner {
-        msg!("Guardian này là owner của ví");
+        debug_msg!("Guardian này là owner của ví");
     }
     
     Ok(())
@@ -94,7 +176,7 @@ pub fn add_guardian(
 pub struct RemoveGuardian<'info> {
     #[account(
         mut,
-        seeds = [b"multisig".as_ref(), &process_credential_id_seed(&multisig.credential_id)],
+        seeds = [b"multisig".as_ref(), &[MULTISIG_SEED_VERSION], &multisig_seed(&multisig.credential_id)],
         bump = multisig.bump
     )]
     pub multisig: Account<'info, MultiSigWallet>,
@@ -107,31 +189,74 @@ pub struct RemoveGuardian<'info> {
     )]
     pub guardian: Account<'info, Guardian>,
     
+    #[account(
+        mut,
+        seeds = [b"guardian_registry".as_ref(), multisig.key().as_ref()],
+        bump = guardian_registry.bump,
+        realloc = GuardianRegistry::space(guardian_registry.guardian_ids.len().saturating_sub(1)),
+        realloc::payer = owner,
+        realloc::zero = false
+    )]
+    pub guardian_registry: Account<'info, GuardianRegistry>,
+    
     /// CHECK: Không còn cần thiết nhưng giữ lại để tương thích
     pub guardian_pubkey: AccountInfo<'info>,
     
    
     #[account(
+        mut,
         seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &owner_guardian_id.to_le_bytes()],
-        constraint = owner_guardian.is_owner == true,
+        constraint = owner_guardian.has_permission(Guardian::PERMISSION_MANAGE_GUARDIANS) @ WalletError::MissingPermission,
+        constraint = owner_guardian.is_active @ WalletError::InactiveGuardian,
         bump = owner_guardian.bump
     )]
     pub owner_guardian: Account<'info, Guardian>,
     
+    pub guardian_signer: Option<Signer<'info>>,
+    
     #[account(mut)]
     pub owner: Signer<'info>,
     
+    /// CHECK: Sysvar instructions, được kiểm tra qua address
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instruction_sysvar: AccountInfo<'info>,
+    
+    pub clock: Sysvar<'info, Clock>,
+    
     pub system_program: Program<'info, System>,
 }
 
-pub fn remove_guardian(ctx: Context<RemoveGuardian>) -> Result<()> {
+pub fn remove_guardian(
+    ctx: Context<RemoveGuardian>,
+    assertion: Option<WebAuthnAssertion>,
+) -> Result<()> {
     let multisig = &mut ctx.accounts.multisig;
     
+    // Chỉ owner mới được xoá owner khác
+    require!(
+        !ctx.accounts.guardian.is_owner || ctx.accounts.owner_guardian.is_owner,
+        WalletError::MissingPermission
+    );
+    
+    verify_guardian_auth(
+        &mut ctx.accounts.owner_guardian,
+        multisig,
+        &ctx.accounts.instruction_sysvar,
+        ctx.accounts.guardian_signer.as_ref().map(|signer| signer.key()),
+        assertion.as_ref(),
+        &format!("remove_guardian:guardian_{}", ctx.accounts.guardian.guardian_id),
+        ctx.accounts.guardian.key()
+    )?;
    
-    require!(multisig.guardian_count > 0, WalletError::GuardianError);
-    multisig.guardian_count -= 1;
+    multisig.on_guardian_removed(&ctx.accounts.guardian)?;
+    ctx.accounts.guardian_registry.remove(ctx.accounts.guardian.guardian_id);
 
-    msg!("Guardian đã được xóa");
+    emit!(GuardianRemoved {
+        multisig: multisig.key(),
+        guardian_id: ctx.accounts.guardian.guardian_id,
+    });
+
+    debug_msg!("Guardian đã được xóa");
     Ok(())
 }
 
@@ -140,7 +265,7 @@ pub fn remove_guardian(ctx: Context<RemoveGuardian>) -> Result<()> {
 pub struct UpdateGuardianStatus<'info> {
     #[account(
         mut,
-        seeds = [b"multisig".as_ref(), &process_credential_id_seed(&multisig.credential_id)],
+        seeds = [b"multisig".as_ref(), &[MULTISIG_SEED_VERSION], &multisig_seed(&multisig.credential_id)],
         bump = multisig.bump
     )]
     pub multisig: Account<'info, MultiSigWallet>,
@@ -155,101 +280,266 @@ pub struct UpdateGuardianStatus<'info> {
     /// CHECK: Không còn cần thiết nhưng giữ lại để tương thích
     pub guardian_pubkey: AccountInfo<'info>,
     
-    /// Tài khoản guardian của người gọi, phải là owner
+    /// Tài khoản guardian của người gọi, phải có quyền quản lý guardian
     #[account(
+        mut,
         seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &owner_guardian_id.to_le_bytes()],
-        constraint = owner_guardian.is_owner == true,
+        constraint = owner_guardian.has_permission(Guardian::PERMISSION_MANAGE_GUARDIANS) @ WalletError::MissingPermission,
+        constraint = owner_guardian.is_active @ WalletError::InactiveGuardian,
         bump = owner_guardian.bump
     )]
     pub owner_guardian: Account<'info, Guardian>,
     
+    pub guardian_signer: Option<Signer<'info>>,
+    
     #[account(mut)]
     pub owner: Signer<'info>,
+    
+    /// CHECK: Sysvar instructions, được kiểm tra qua address
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instruction_sysvar: AccountInfo<'info>,
+    
+    pub clock: Sysvar<'info, Clock>,
 }
 
 
 pub fn update_guardian_status(
     ctx: Context<UpdateGuardianStatus>,
     is_active: bool,
+    assertion: Option<WebAuthnAssertion>,
 ) -> Result<()> {
     let guardian = &mut ctx.accounts.guardian;
     
   
-    require!(ctx.accounts.owner_guardian.is_owner, WalletError::InvalidOperation);
+    // Chỉ owner mới được đổi trạng thái của owner khác
+    require!(
+        !guardian.is_owner || ctx.accounts.owner_guardian.is_owner,
+        WalletError::MissingPermission
+    );
+    
+    verify_guardian_auth(
+        &mut ctx.accounts.owner_guardian,
+        &ctx.accounts.multisig,
+        &ctx.accounts.instruction_sysvar,
+        ctx.accounts.guardian_signer.as_ref().map(|signer| signer.key()),
+        assertion.as_ref(),
+        &format!("update_guardian_status:guardian_{},active_{}", guardian.guardian_id, is_active),
+        guardian.key()
+    )?;
+    
+    ctx.accounts.multisig.on_guardian_status_changed(guardian, is_active)?;
     
     // Cập nhật trạng thái
     guardian.is_active = is_active;
 
-    msg!("Trạng thái guardian đã được cập nhật");
+    emit!(GuardianStatusChanged {
+        multisig: ctx.accounts.multisig.key(),
+        guardian_id: guardian.guardian_id,
+        is_active,
+    });
+
+    debug_msg!("Trạng thái guardian đã được cập nhật");
     Ok(())
 }
 
-#[derive(Accounts)]
-#[instruction(old_guardian_id: u64, new_guardian_id: u64, recovery_hash_intermediate: [u8; 32])]
-pub struct RecoverAccessByGuardian<'info> {
-    #[account(
-        mut,
-        seeds = [b"multisig".as_ref(), &process_credential_id_seed(&multisig.credential_id)],
-        bump = multisig.bump
-    )]
-    pub multisig: Account<'info, MultiSigWallet>,
-    
-
-    #[account(
-        mut,
-        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &old_guardian_id.to_le_bytes()],
-        bump = old_guardian.bump,
-        constraint = old_guardian.is_owner == true
-    )]
-    pub old_guardian: Account<'info, Guardian>,
-    
-    /// CHECK: Không còn cần thiết nhưng giữ lại để tương thích
-    pub old_guardian_pubkey: AccountInfo<'info>,
+/// Đường xác thực duy nhất cho mọi instruction hành động thay mặt guardian.
+///
+/// Guardian có signer ed25519 phải ký transaction; guardian có WebAuthn key phải
+/// gửi assertion cho `SigningPayload` của `action` trên `target`. Nonce của guardian
+/// tăng sau mỗi lần xác thực để assertion không bị phát lại.
+pub fn verify_guardian_auth(
+    guardian: &mut Guardian,
+    multisig: &Account<MultiSigWallet>,
+    instruction_sysvar: &AccountInfo,
+    signer: Option<Pubkey>,
+    assertion: Option<&WebAuthnAssertion>,
+    action: &str,
+    target: Pubkey,
+) -> Result<()> {
+    if let Some(expected_signer) = guardian.auth.signer() {
+        require!(
+            signer == Some(expected_signer),
+            WalletError::GuardianSignerMissing
+        );
+    }
     
-
-    #[account(
-        mut,
-        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &new_guardian_id.to_le_bytes()],
-        bump = new_guardian.bump
-    )]
-    pub new_guardian: Account<'info, Guardian>,
+    if let Some(webauthn_pubkey) = guardian.auth.webauthn_pubkey() {
+        let assertion = assertion.ok_or(WalletError::WebAuthnAssertionMissing)?;
+        
+        let payload = SigningPayload {
+            version: SIGNING_PAYLOAD_VERSION,
+            cluster: CLUSTER_ID,
+            program_id: crate::ID,
+            multisig: multisig.key(),
+            target,
+            action_hash: hash(action.as_bytes()).to_bytes(),
+            guardian_id: guardian.guardian_id,
+            nonce: guardian.nonce,
+        };
+        debug_msg!("Action: {}, nonce: {}", action, guardian.nonce);
+        
+        verify_webauthn_assertion(
+            instruction_sysvar,
+            &webauthn_pubkey,
+            &multisig.rp_id_hash,
+            &mut guardian.sign_count,
+            assertion,
+            &payload.try_to_vec()?
+        )?;
+    }
     
-    /// CHECK: Không còn cần thiết nhưng giữ lại để tương thích
-    pub new_guardian_pubkey: AccountInfo<'info>,
+    guardian.nonce = guardian.nonce
+        .checked_add(1)
+        .ok_or(WalletError::ArithmeticOverflow)?;
     
-    pub system_program: Program<'info, System>,
+    Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-pub fn recover_access_by_guardian(
-    ctx: Context<RecoverAccessByGuardian>,
-    recovery_hash_intermediate: [u8; 32],
-    new_webauthn_pubkey: [u8; 33],
-) -> Result<()> {
-    let multisig = &mut ctx.accounts.multisig;
-    let old_guardian = &mut ctx.accounts.old_guardian;
-    let new_guardian = &mut ctx.accounts.new_guardian;
-    
+    /// Dữ liệu của một tài khoản giả để dựng AccountInfo trong test
+    struct TestAccount {
+        key: Pubkey,
+        owner: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+    }
 
-    require!(old_guardian.wallet == multisig.key(), WalletError::InvalidGuardian);
-    require!(old_guardian.is_active, WalletError::InactiveGuardian);
-    
-  
-    let hash_result = hash(&recovery_hash_intermediate);
-    let final_hash: [u8; 32] = hash_result.to_bytes();
-    
-    require!(old_guardian.recovery_hash == final_hash, WalletError::InvalidRecoveryKey);
-    
-    
-    old_guardian.is_owner = false;
-    old_guardian.webauthn_pubkey = None;
-    
-    new_guardian.is_owner = true;
-    new_guardian.webauthn_pubkey = Some(new_webauthn_pubkey);
-    
+    impl TestAccount {
+        fn multisig() -> TestAccount {
+            let wallet = MultiSigWallet {
+                threshold: 1,
+                guardian_count: 1,
+                recovery_nonce: 0,
+                bump: 255,
+                transaction_nonce: 0,
+                last_transaction_timestamp: 0,
+                owner: Pubkey::new_unique(),
+                credential_id: String::new(),
+                rp_id_hash: [7; 32],
+                rejection_threshold: 0,
+                proposal_ttl: 0,
+                active_guardian_count: 1,
+                owner_count: 1,
+                recovery_delay: 0,
+                recovery_threshold: 1,
+                destination_policy: DestinationPolicy::Unrestricted,
+                next_proposal_id: 0,
+                weight_threshold: 0,
+                active_weight: 1,
+                max_guardians: DEFAULT_MAX_GUARDIANS,
+            };
+            let mut data = Vec::new();
+            wallet.try_serialize(&mut data).unwrap();
+            TestAccount { key: Pubkey::new_unique(), owner: crate::ID, lamports: 0, data }
+        }
 
-    multisig.recovery_nonce += 1;
-    
-    msg!("Quyền truy cập đã được khôi phục thành công thông qua guardian");
-    Ok(())
-}
\ No newline at end of file
+        fn empty() -> TestAccount {
+            TestAccount {
+                key: Pubkey::new_unique(),
+                owner: Pubkey::default(),
+                lamports: 0,
+                data: Vec::new(),
+            }
+        }
+
+        fn info(&mut self) -> AccountInfo<'_> {
+            AccountInfo::new(&self.key, false, false, &mut self.lamports, &mut self.data, &self.owner, false, 0)
+        }
+    }
+
+    fn guardian(auth: GuardianAuth) -> Guardian {
+        Guardian {
+            wallet: Pubkey::new_unique(),
+            guardian_id: 1,
+            name: String::new(),
+            is_active: true,
+            recovery_hash: [0; 32],
+            is_owner: false,
+            permissions: Guardian::PERMISSION_APPROVE,
+            weight: 1,
+            auth,
+            sign_count: 0,
+            nonce: 0,
+            bump: 255,
+        }
+    }
+
+    fn verify(
+        guardian: &mut Guardian,
+        signer: Option<Pubkey>,
+        assertion: Option<&WebAuthnAssertion>
+    ) -> Result<()> {
+        let mut multisig_account = TestAccount::multisig();
+        let mut sysvar_account = TestAccount::empty();
+        let multisig_info = multisig_account.info();
+        let multisig = Account::<MultiSigWallet>::try_from(&multisig_info).unwrap();
+        let sysvar_info = sysvar_account.info();
+
+        verify_guardian_auth(guardian, &multisig, &sysvar_info, signer, assertion, "test", Pubkey::new_unique())
+    }
+
+    #[test]
+    fn signer_guardian_must_sign_and_nonce_increments() {
+        let signer = Pubkey::new_unique();
+        let mut guardian = guardian(GuardianAuth::Ed25519 { signer });
+
+        assert_eq!(
+            verify(&mut guardian, None, None).err(),
+            Some(WalletError::GuardianSignerMissing.into())
+        );
+        assert_eq!(
+            verify(&mut guardian, Some(Pubkey::new_unique()), None).err(),
+            Some(WalletError::GuardianSignerMissing.into())
+        );
+        assert_eq!(guardian.nonce, 0);
+
+        verify(&mut guardian, Some(signer), None).unwrap();
+        verify(&mut guardian, Some(signer), None).unwrap();
+        assert_eq!(guardian.nonce, 2);
+    }
+
+    #[test]
+    fn webauthn_guardian_requires_an_assertion() {
+        let mut guardian = guardian(GuardianAuth::WebAuthn { pubkey: [2; 33] });
+
+        assert_eq!(
+            verify(&mut guardian, Some(Pubkey::new_unique()), None).err(),
+            Some(WalletError::WebAuthnAssertionMissing.into())
+        );
+        assert_eq!(guardian.nonce, 0);
+    }
+
+    #[test]
+    fn webauthn_assertion_for_another_rp_is_rejected() {
+        let mut guardian = guardian(GuardianAuth::WebAuthn { pubkey: [2; 33] });
+        let assertion = WebAuthnAssertion {
+            authenticator_data: vec![0; 37],
+            client_data_json: Vec::new(),
+        };
+
+        assert_eq!(
+            verify(&mut guardian, None, Some(&assertion)).err(),
+            Some(WalletError::RpIdMismatch.into())
+        );
+        assert_eq!(guardian.nonce, 0);
+    }
+
+    #[test]
+    fn guardian_with_both_auths_needs_signer_and_assertion() {
+        let signer = Pubkey::new_unique();
+        let mut guardian = guardian(GuardianAuth::Both { webauthn_pubkey: [2; 33], signer });
+
+        assert_eq!(
+            verify(&mut guardian, None, None).err(),
+            Some(WalletError::GuardianSignerMissing.into())
+        );
+        assert_eq!(
+            verify(&mut guardian, Some(signer), None).err(),
+            Some(WalletError::WebAuthnAssertionMissing.into())
+        );
+        assert_eq!(guardian.nonce, 0);
+    }
+}
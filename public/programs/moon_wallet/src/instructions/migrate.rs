@@ -0,0 +1,282 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+use crate::state::*;
+use crate::errors::*;
+use crate::instructions::wallet::{
+    legacy_credential_id_seed,
+    legacy_multisig_address,
+    multisig_seed,
+    MULTISIG_SEED_VERSION
+};
+use crate::instructions::execute::transfer_checked_signed;
+use anchor_spl::token_interface::{self, CloseAccount, Mint, TokenAccount, TokenInterface};
+
+// Ví cũ được đọc theo layout cũ (LegacyMultiSigWallet/LegacyGuardian). Các trường mới của ví
+// do owner cũ truyền vào khi migrate_multisig; migrate_guardian không cần quyền vì kết quả chỉ
+// phụ thuộc vào dữ liệu đã lưu. Đề xuất đang chờ của ví cũ không được chuyển.
+
+#[derive(Accounts)]
+pub struct MigrateMultisig<'info> {
+    #[account(
+        mut,
+        seeds = [b"multisig".as_ref(), &legacy_credential_id_seed(&legacy_multisig.credential_id)],
+        bump = legacy_multisig.bump,
+        close = multisig
+    )]
+    pub legacy_multisig: Account<'info, LegacyMultiSigWallet>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = MultiSigWallet::space(legacy_multisig.credential_id.len()),
+        seeds = [b"multisig".as_ref(), &[MULTISIG_SEED_VERSION], &multisig_seed(&legacy_multisig.credential_id)],
+        bump
+    )]
+    pub multisig: Account<'info, MultiSigWallet>,
+
+    // Ví cũ chưa có registry, các guardian được thêm vào khi migrate_guardian
+    #[account(
+        init,
+        payer = payer,
+        space = GuardianRegistry::space(0),
+        seeds = [b"guardian_registry".as_ref(), multisig.key().as_ref()],
+        bump
+    )]
+    pub guardian_registry: Account<'info, GuardianRegistry>,
+
+    /// Người đã tạo ví cũ, là người duy nhất được chọn cấu hình cho ví mới
+    #[account(mut, address = legacy_multisig.owner @ WalletError::InvalidOperation)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Số guardian được tính lại khi từng guardian được migrate_guardian
+pub fn migrate_multisig(
+    ctx: Context<MigrateMultisig>,
+    rp_id: String,
+    recovery_delay: i64,
+    recovery_threshold: u8,
+) -> Result<()> {
+    let legacy_multisig = &ctx.accounts.legacy_multisig;
+    let multisig = &mut ctx.accounts.multisig;
+
+    require!(!rp_id.is_empty(), WalletError::InvalidConfig);
+    require!(recovery_delay >= 0, WalletError::InvalidConfig);
+    require!(recovery_threshold > 0, WalletError::InvalidConfig);
+
+    multisig.set_inner(MultiSigWallet {
+        threshold: legacy_multisig.threshold,
+        guardian_count: 0,
+        recovery_nonce: legacy_multisig.recovery_nonce,
+        bump: ctx.bumps.multisig,
+        transaction_nonce: legacy_multisig.transaction_nonce,
+        last_transaction_timestamp: legacy_multisig.last_transaction_timestamp,
+        owner: legacy_multisig.owner,
+        credential_id: legacy_multisig.credential_id.clone(),
+        rp_id_hash: hash(rp_id.as_bytes()).to_bytes(),
+        rejection_threshold: 0,
+        proposal_ttl: 0,
+        active_guardian_count: 0,
+        owner_count: 0,
+        recovery_delay,
+        recovery_threshold,
+        destination_policy: DestinationPolicy::Unrestricted,
+        next_proposal_id: 0,
+        weight_threshold: 0,
+        active_weight: 0,
+        max_guardians: DEFAULT_MAX_GUARDIANS,
+    });
+
+    ctx.accounts.guardian_registry.set_inner(GuardianRegistry {
+        multisig: multisig.key(),
+        guardian_ids: Vec::new(),
+        bump: ctx.bumps.guardian_registry,
+    });
+
+    // Toàn bộ lamports của ví cũ được chuyển sang ví mới qua `close = multisig`
+    debug_msg!("Đã migrate ví {} sang {}", legacy_multisig.key(), multisig.key());
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(guardian_id: u64)]
+pub struct MigrateGuardian<'info> {
+    #[account(
+        mut,
+        seeds = [b"multisig".as_ref(), &[MULTISIG_SEED_VERSION], &multisig_seed(&multisig.credential_id)],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, MultiSigWallet>,
+
+    /// CHECK: Chỉ dùng làm seed, được kiểm tra là PDA cũ của `multisig`
+    #[account(address = legacy_multisig_address(&multisig.credential_id).0 @ WalletError::MultisigMismatch)]
+    pub legacy_multisig: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"guardian".as_ref(), legacy_multisig.key().as_ref(), &guardian_id.to_le_bytes()],
+        bump = legacy_guardian.bump,
+        constraint = legacy_guardian.wallet == legacy_multisig.key() @ WalletError::MultisigMismatch,
+        close = payer
+    )]
+    pub legacy_guardian: Account<'info, LegacyGuardian>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = Guardian::SPACE,
+        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &guardian_id.to_le_bytes()],
+        bump
+    )]
+    pub guardian: Account<'info, Guardian>,
+
+    #[account(
+        mut,
+        seeds = [b"guardian_registry".as_ref(), multisig.key().as_ref()],
+        bump = guardian_registry.bump,
+        realloc = GuardianRegistry::space(guardian_registry.guardian_ids.len() + 1),
+        realloc::payer = payer,
+        realloc::zero = false
+    )]
+    pub guardian_registry: Account<'info, GuardianRegistry>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Guardian cũ không có WebAuthn key thì không có cách xác thực nào, phải được thêm lại bằng add_guardian
+pub fn migrate_guardian(ctx: Context<MigrateGuardian>, guardian_id: u64) -> Result<()> {
+    let multisig = &mut ctx.accounts.multisig;
+    let legacy_guardian = &ctx.accounts.legacy_guardian;
+    let multisig_key = multisig.key();
+
+    let pubkey = legacy_guardian.webauthn_pubkey.ok_or(WalletError::WebAuthnNotConfigured)?;
+    require!(
+        multisig.guardian_count < multisig.max_guardians,
+        WalletError::LimitExceeded
+    );
+
+    // Guardian cũ giữ quyền như trước: owner toàn quyền, guardian khác không quản lý guardian
+    let permissions = if legacy_guardian.is_owner {
+        Guardian::ALL_PERMISSIONS
+    } else {
+        Guardian::ALL_PERMISSIONS & !Guardian::PERMISSION_MANAGE_GUARDIANS
+    };
+
+    ctx.accounts.guardian.set_inner(Guardian {
+        wallet: multisig_key,
+        guardian_id,
+        name: legacy_guardian.name.clone(),
+        is_active: legacy_guardian.is_active,
+        recovery_hash: legacy_guardian.recovery_hash,
+        is_owner: legacy_guardian.is_owner,
+        permissions,
+        weight: Guardian::DEFAULT_WEIGHT,
+        auth: GuardianAuth::WebAuthn { pubkey },
+        sign_count: 0,
+        nonce: 0,
+        bump: ctx.bumps.guardian,
+    });
+
+    multisig.guardian_count += 1;
+    if legacy_guardian.is_active {
+        multisig.active_guardian_count += 1;
+        multisig.active_weight += Guardian::DEFAULT_WEIGHT as u16;
+    }
+    if legacy_guardian.is_owner {
+        multisig.owner_count += 1;
+    }
+    ctx.accounts.guardian_registry.guardian_ids.push(guardian_id);
+
+    debug_msg!("Đã migrate guardian {} sang ví {}", guardian_id, multisig_key);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MigrateTokenAccount<'info> {
+    #[account(
+        mut,
+        seeds = [b"multisig".as_ref(), &[MULTISIG_SEED_VERSION], &multisig_seed(&multisig.credential_id)],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, MultiSigWallet>,
+
+    /// CHECK: Chỉ dùng làm authority ký thay, được kiểm tra là PDA cũ của `multisig`
+    #[account(address = legacy_multisig_address(&multisig.credential_id).0 @ WalletError::MultisigMismatch)]
+    pub legacy_multisig: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = legacy_token_account.owner == legacy_multisig.key() @ WalletError::InvalidOwner
+    )]
+    pub legacy_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_account.owner == multisig.key() @ WalletError::InvalidOwner,
+        constraint = token_account.mint == legacy_token_account.mint @ WalletError::InvalidOperation
+    )]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = legacy_token_account.mint @ WalletError::InvalidOperation)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// remaining_accounts: các tài khoản phụ của transfer hook nếu mint có
+/// Chuyển toàn bộ token từ token account của PDA cũ rồi đóng nó, rent trả về ví mới
+pub fn migrate_token_account<'info>(
+    ctx: Context<'_, '_, 'info, 'info, MigrateTokenAccount<'info>>
+) -> Result<()> {
+    let multisig = &ctx.accounts.multisig;
+    let (_, legacy_bump) = legacy_multisig_address(&multisig.credential_id);
+    let legacy_seed = legacy_credential_id_seed(&multisig.credential_id);
+    let seeds = &[
+        b"multisig".as_ref(),
+        &legacy_seed,
+        &[legacy_bump]
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    let amount = ctx.accounts.legacy_token_account.amount;
+
+    if amount > 0 {
+        transfer_checked_signed(
+            &ctx.accounts.token_program,
+            ctx.accounts.legacy_token_account.to_account_info(),
+            &ctx.accounts.token_mint,
+            ctx.accounts.token_account.to_account_info(),
+            ctx.accounts.legacy_multisig.to_account_info(),
+            ctx.remaining_accounts,
+            amount,
+            signer_seeds
+        )?;
+    }
+
+    token_interface::close_account(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.legacy_token_account.to_account_info(),
+                destination: multisig.to_account_info(),
+                authority: ctx.accounts.legacy_multisig.to_account_info(),
+            },
+            signer_seeds,
+        )
+    )?;
+
+    debug_msg!(
+        "Đã migrate {} token ({}) sang {}",
+        amount,
+        ctx.accounts.legacy_token_account.mint,
+        ctx.accounts.token_account.key()
+    );
+
+    Ok(())
+}
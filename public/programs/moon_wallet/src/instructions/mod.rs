@@ -1,7 +1,15 @@
 pub mod wallet;
 pub mod guardian;
 pub mod proposal;
+pub mod execute;
+pub mod migrate;
+pub mod recovery;
+pub mod spending_limit;
 
 pub use wallet::*;
 pub use guardian::*;
-pub use proposal::*; 
\ No newline at end of file
+pub use proposal::*;
+pub use execute::*;
+pub use migrate::*;
+pub use recovery::*;
+pub use spending_limit::*; 
\ No newline at end of file
@@ -1,18 +1,18 @@
 use anchor_lang::prelude::*;
 use crate::state::*;
 use crate::errors::*;
-use crate::instructions::wallet::process_credential_id_seed;
-use anchor_lang::solana_program::sysvar::instructions::load_instruction_at_checked;
+use crate::events::*;
+use crate::instructions::wallet::{multisig_seed, MULTISIG_SEED_VERSION};
+use crate::instructions::guardian::verify_guardian_auth;
+use crate::webauthn::WebAuthnAssertion;
 use anchor_lang::solana_program::hash::hash;
-use std::str::FromStr;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer as SplTransfer};
 
 #[derive(Accounts)]
-#[instruction(proposal_id: u64, description: String, proposer_guardian_id: u64)]
+#[instruction(description: String, proposer_guardian_id: u64, actions: Vec<ProposalAction>)]
 pub struct CreateProposal<'info> {
     #[account(
         mut,
-        seeds = [b"multisig".as_ref(), &process_credential_id_seed(&multisig.credential_id)],
+        seeds = [b"multisig".as_ref(), &[MULTISIG_SEED_VERSION], &multisig_seed(&multisig.credential_id)],
         bump = multisig.bump
     )]
     pub multisig: Account<'info, MultiSigWallet>,
@@ -24,110 +24,302 @@ pub struct CreateProposal<'info> {
                 32 +    
                 8 +  
                 32 +  
+                32 +
                 4 + description.len() + 
-                4 + 15 + 
-                1 + 8 + 32 + 32 + 
+                4 + actions.iter().map(ProposalAction::space).sum::<usize>() +
+                32 +
+                1 +
                 1 +  
                 8 +  
                 1 + 8 + 
+                1 + 8 +
                 1 +  
                 1 +  
+                2 +
+                2 +
+                1 +
+                1 +
+                1 +
+                1 +
                 1,    
         seeds = [
             b"proposal".as_ref(), 
             multisig.key().as_ref(), 
-            &proposal_id.to_le_bytes()
+            &multisig.next_proposal_id.to_le_bytes()
         ],
         bump
     )]
     pub proposal: Account<'info, TransactionProposal>,
     
     #[account(
+        mut,
         seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &proposer_guardian_id.to_le_bytes()],
-        bump = proposer_guardian.bump
+        bump = proposer_guardian.bump,
+        constraint = proposer_guardian.has_permission(Guardian::PERMISSION_PROPOSE) @ WalletError::MissingPermission
     )]
     pub proposer_guardian: Account<'info, Guardian>,
     
+    pub guardian_signer: Option<Signer<'info>>,
+    
     #[account(mut)]
     pub payer: Signer<'info>,
     
+    /// CHECK: Sysvar instructions, được kiểm tra qua address
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instruction_sysvar: AccountInfo<'info>,
+    
     pub clock: Sysvar<'info, Clock>,
     pub system_program: Program<'info, System>,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn create_proposal(
     ctx: Context<CreateProposal>, 
-    proposal_id: u64,
     description: String,
     _proposer_guardian_id: u64,
-    action: String,
-    params: ActionParams
+    actions: Vec<ProposalAction>,
+    expires_at: Option<i64>,
+    assertion: Option<WebAuthnAssertion>
 ) -> Result<()> {
     let multisig = &ctx.accounts.multisig;
-    let proposer_guardian = &ctx.accounts.proposer_guardian;
+    let proposer_guardian = &mut ctx.accounts.proposer_guardian;
     let clock = &ctx.accounts.clock;
+    let proposal_id = multisig.next_proposal_id;
     
     require!(
-        proposer_guardian.is_active == true,
+        proposer_guardian.is_active,
         WalletError::InactiveGuardian
     );
     
+    // Ví đang thiếu guardian hoạt động thì không đề xuất nào đạt được ngưỡng
+    multisig.validate_threshold(multisig.threshold)?;
+    multisig.validate_weight_threshold(multisig.required_weight())?;
+    
     require!(
-        action == "transfer" || action == "transfer_token",
+        !actions.is_empty() && actions.len() <= MAX_PROPOSAL_ACTIONS,
+        WalletError::InvalidOperation
+    );
+    // Invoke dùng remaining_accounts theo đúng thứ tự nên không gộp chung với hành động khác
+    require!(
+        actions.len() == 1 || !actions.iter().any(|action| matches!(action, ProposalAction::Invoke { .. })),
         WalletError::UnsupportedAction
     );
+    for action in &actions {
+        validate_action(multisig, action)?;
+    }
     
-    match action.as_str() {
-        "transfer" => {
-            require!(
-                params.amount.is_some() && params.destination.is_some(),
-                WalletError::InvalidOperation
-            );
-        },
-        "transfer_token" => {
-            require!(
-                params.token_mint.is_some() && params.token_amount.is_some() && params.destination.is_some(),
-                WalletError::InvalidOperation
-            );
+    let contents_hash = TransactionProposal::compute_contents_hash(&multisig.key(), &actions)?;
+    
+    // Không ký theo proposal_id để client không phải đoán ID sẽ được cấp
+    verify_guardian_auth(
+        proposer_guardian,
+        multisig,
+        &ctx.accounts.instruction_sysvar,
+        ctx.accounts.guardian_signer.as_ref().map(|signer| signer.key()),
+        assertion.as_ref(),
+        &format!("create:contents_{}", to_hex(&contents_hash)),
+        multisig.key()
+    )?;
+    
+    // Đề xuất không có hạn riêng dùng TTL mặc định của ví (0 = không hết hạn)
+    let expires_at = match expires_at {
+        Some(expires_at) => {
+            require!(expires_at > clock.unix_timestamp, WalletError::ProposalExpired);
+            Some(expires_at)
         },
-        _ => return Err(WalletError::UnsupportedAction.into())
-    }
+        None if multisig.proposal_ttl > 0 => Some(
+            clock.unix_timestamp
+                .checked_add(multisig.proposal_ttl)
+                .ok_or(WalletError::ArithmeticOverflow)?
+        ),
+        None => None,
+    };
     
     let proposal = &mut ctx.accounts.proposal;
     
     proposal.multisig = multisig.key();
     proposal.proposal_id = proposal_id;
     proposal.proposer = proposer_guardian.key();
+    proposal.payer = ctx.accounts.payer.key();
     proposal.description = description;
-    proposal.action = action;
-    proposal.params = params;
+    proposal.actions = actions;
+    proposal.contents_hash = contents_hash;
+    proposal.executed_actions = 0;
     proposal.status = ProposalStatus::Pending;
     proposal.created_at = clock.unix_timestamp;
     proposal.executed_at = None;
+    proposal.expires_at = expires_at;
     proposal.signatures_count = 0; 
     proposal.required_signatures = multisig.threshold;
+    proposal.approval_weight = 0;
+    proposal.required_weight = multisig.required_weight();
+    proposal.rejections_count = 0;
+    proposal.required_rejections = multisig.rejection_quorum();
+    proposal.closed_signatures = 0;
+    proposal.closed_rejections = 0;
     proposal.bump = ctx.bumps.proposal;
     
-    msg!("Đã tạo đề xuất mới với ID: {}", proposal_id);
-    msg!("Hành động: {}", proposal.action);
-    if let Some(amount) = proposal.params.amount {
-        msg!("Số lượng SOL: {}", amount as f64 / 1_000_000_000.0);
-    }
-    if let Some(token_amount) = proposal.params.token_amount {
-        msg!("Số lượng token: {}", token_amount);
-    }
-    if let Some(token_mint) = proposal.params.token_mint {
-        msg!("Token mint: {}", token_mint);
+    ctx.accounts.multisig.next_proposal_id = proposal_id
+        .checked_add(1)
+        .ok_or(WalletError::ArithmeticOverflow)?;
+    
+    emit!(ProposalCreated {
+        multisig: proposal.multisig,
+        proposal_id,
+        proposer: proposal.proposer,
+        action_count: proposal.actions.len() as u8,
+        contents_hash: proposal.contents_hash,
+        required_signatures: proposal.required_signatures,
+        required_weight: proposal.required_weight,
+        expires_at: proposal.expires_at,
+    });
+    
+    debug_msg!("Đã tạo đề xuất mới với ID: {}", proposal_id);
+    for action in &proposal.actions {
+        log_action(action);
     }
-    if let Some(destination) = proposal.params.destination {
-        msg!("Đích đến: {}", destination);
+    
+    Ok(())
+}
+
+fn validate_action(multisig: &MultiSigWallet, action: &ProposalAction) -> Result<()> {
+    match action {
+        ProposalAction::TransferSol { amount, .. } |
+        ProposalAction::TransferToken { amount, .. } => {
+            require!(*amount > 0, WalletError::InvalidOperation);
+        },
+        ProposalAction::Invoke { instructions } => {
+            require!(!instructions.is_empty(), WalletError::InvalidOperation);
+            // Không cho phép gọi lại chính chương trình để vượt qua luồng phê duyệt
+            require!(
+                instructions.iter().all(|ix| ix.program_id != crate::ID),
+                WalletError::UnsupportedAction
+            );
+            // Không kiểm tra được đích của Invoke nên không thể dùng khi chỉ cho phép sổ địa chỉ
+            require!(
+                multisig.destination_policy != DestinationPolicy::AllowlistOnly,
+                WalletError::DestinationNotAllowed
+            );
+        },
+        ProposalAction::ChangeThreshold { threshold, .. } => {
+            multisig.validate_threshold(*threshold)?;
+        },
+        ProposalAction::ChangeWeightThreshold { weight_threshold } => {
+            multisig.validate_weight_threshold(*weight_threshold)?;
+        },
+        ProposalAction::AddGuardian { name, is_owner, permissions, weight, auth, .. } => {
+            require!(name.len() <= 32, WalletError::NameTooLong);
+            require!(!*is_owner || auth.webauthn_pubkey().is_some(), WalletError::WebAuthnNotConfigured);
+            Guardian::validate_permissions(*permissions, *is_owner)?;
+            Guardian::resolve_weight(*weight)?;
+            require!(multisig.guardian_count < multisig.max_guardians, WalletError::LimitExceeded);
+        },
+        ProposalAction::RemoveGuardian { .. } => {},
+        ProposalAction::ChangeRecoveryDelay { recovery_delay } => {
+            require!(*recovery_delay >= 0, WalletError::InvalidConfig);
+        },
+        ProposalAction::ChangeRecoveryThreshold { recovery_threshold } => {
+            multisig.validate_threshold(*recovery_threshold)?;
+        },
+        ProposalAction::ChangeMaxGuardians { max_guardians } => {
+            multisig.validate_max_guardians(*max_guardians)?;
+        },
+        ProposalAction::SetSpendingLimit { amount, period, destinations, .. } => {
+            require!(*amount > 0 && *period > 0, WalletError::InvalidConfig);
+            require!(
+                destinations.len() <= MAX_SPENDING_LIMIT_DESTINATIONS,
+                WalletError::LimitExceeded
+            );
+        },
+        ProposalAction::RemoveSpendingLimit { .. } => {},
+        ProposalAction::AddAddressBookEntry { label, .. } => {
+            require!(label.len() <= 32, WalletError::NameTooLong);
+        },
+        ProposalAction::RemoveAddressBookEntry { .. } => {},
+        ProposalAction::SetDestinationPolicy { policy } => {
+            if let DestinationPolicy::ElevatedThreshold { threshold } = policy {
+                // Khi bỏ phiếu theo trọng số, ngưỡng nâng cao tính bằng trọng số phê duyệt
+                if multisig.required_weight() > 0 {
+                    require!(*threshold as u16 >= multisig.required_weight(), WalletError::InvalidThreshold);
+                    multisig.validate_weight_threshold(*threshold as u16)?;
+                } else {
+                    require!(*threshold >= multisig.threshold, WalletError::InvalidThreshold);
+                    multisig.validate_threshold(*threshold)?;
+                }
+            }
+        },
+        ProposalAction::ChangeGuardianWeight { weight, .. } => {
+            Guardian::resolve_weight(Some(*weight))?;
+        },
     }
     
     Ok(())
 }
 
+fn log_action(action: &ProposalAction) {
+    debug_msg!("Hành động: {}", action.name());
+    match action {
+        ProposalAction::TransferSol { amount, destination } => {
+            debug_msg!("Số lượng SOL: {}", *amount as f64 / 1_000_000_000.0);
+            debug_msg!("Đích đến: {}", destination);
+        },
+        ProposalAction::TransferToken { mint, amount, destination, recipient_token_account } => {
+            debug_msg!("Số lượng token: {}", amount);
+            debug_msg!("Token mint: {}", mint);
+            debug_msg!("Đích đến: {}", destination);
+            if let Some(recipient_token_account) = recipient_token_account {
+                debug_msg!("Token account nhận: {}", recipient_token_account);
+            }
+        },
+        ProposalAction::Invoke { instructions } => {
+            for ix in instructions {
+                debug_msg!("Gọi chương trình {} với {} tài khoản", ix.program_id, ix.accounts.len());
+            }
+        },
+        ProposalAction::ChangeThreshold { threshold, rejection_threshold } => {
+            debug_msg!("Ngưỡng mới: {}, ngưỡng từ chối: {}", threshold, rejection_threshold);
+        },
+        ProposalAction::ChangeWeightThreshold { weight_threshold } => {
+            debug_msg!("Ngưỡng trọng số mới: {}", weight_threshold);
+        },
+        ProposalAction::AddGuardian { guardian_id, name, is_owner, permissions, .. } => {
+            debug_msg!("Thêm guardian {} ({}), owner: {}, quyền: {:#07b}", guardian_id, name, is_owner, permissions);
+        },
+        ProposalAction::RemoveGuardian { guardian_id } => {
+            debug_msg!("Xoá guardian {}", guardian_id);
+        },
+        ProposalAction::ChangeRecoveryDelay { recovery_delay } => {
+            debug_msg!("Thời gian chờ khôi phục mới: {} giây", recovery_delay);
+        },
+        ProposalAction::ChangeRecoveryThreshold { recovery_threshold } => {
+            debug_msg!("Số guardian cần phê duyệt khôi phục mới: {}", recovery_threshold);
+        },
+        ProposalAction::ChangeMaxGuardians { max_guardians } => {
+            debug_msg!("Số guardian tối đa mới: {}", max_guardians);
+        },
+        ProposalAction::SetSpendingLimit { mint, amount, period, .. } => {
+            debug_msg!("Hạn mức chi tiêu ({}): {} mỗi {} giây", mint, amount, period);
+        },
+        ProposalAction::RemoveSpendingLimit { mint } => {
+            debug_msg!("Xoá hạn mức chi tiêu ({})", mint);
+        },
+        ProposalAction::AddAddressBookEntry { address, label } => {
+            debug_msg!("Thêm {} ({}) vào sổ địa chỉ", address, label);
+        },
+        ProposalAction::RemoveAddressBookEntry { address } => {
+            debug_msg!("Xoá {} khỏi sổ địa chỉ", address);
+        },
+        ProposalAction::SetDestinationPolicy { .. } => {
+            debug_msg!("Đổi chính sách địa chỉ đích");
+        },
+        ProposalAction::ChangeGuardianWeight { guardian_id, weight } => {
+            debug_msg!("Đổi trọng số guardian {} thành {}", guardian_id, weight);
+        },
+    }
+}
+
 #[derive(Accounts)]
-#[instruction(proposal_id: u64, guardian_id: u64, timestamp: i64)]
+#[instruction(proposal_id: u64, guardian_id: u64)]
 pub struct ApproveProposal<'info> {
     #[account(mut)]
     pub multisig: Account<'info, MultiSigWallet>,
@@ -162,13 +354,30 @@ pub struct ApproveProposal<'info> {
     )]
     pub signature: Account<'info, ProposalSignature>,
     
+    /// CHECK: PDA từ chối của cùng guardian, phải chưa được tạo để một guardian
+    /// không vừa phê duyệt vừa từ chối một đề xuất
     #[account(
+        seeds = [
+            b"rejection".as_ref(),
+            proposal.key().as_ref(),
+            &guardian_id.to_le_bytes()
+        ],
+        bump,
+        constraint = existing_rejection.data_is_empty() @ WalletError::AlreadyRejected
+    )]
+    pub existing_rejection: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
         seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &guardian_id.to_le_bytes()],
         bump = guardian.bump,
-        constraint = guardian.is_active @ WalletError::InactiveGuardian
+        constraint = guardian.is_active @ WalletError::InactiveGuardian,
+        constraint = guardian.has_permission(Guardian::PERMISSION_APPROVE) @ WalletError::MissingPermission
     )]
     pub guardian: Account<'info, Guardian>,
     
+    pub guardian_signer: Option<Signer<'info>>,
+    
     #[account(mut)]
     pub payer: Signer<'info>,
     
@@ -180,30 +389,22 @@ pub struct ApproveProposal<'info> {
 }
 
 
-fn standardize_pubkey(pubkey: &[u8; 33]) -> [u8; 33] {
-
-    msg!("Standardizing pubkey: {}", to_hex(pubkey));
-    
-    *pubkey
-}
-
 pub fn approve_proposal(
     ctx: Context<ApproveProposal>, 
     proposal_id: u64,
     guardian_id: u64,
-    timestamp: i64,
-    message: Vec<u8>
+    contents_hash: [u8; 32],
+    assertion: Option<WebAuthnAssertion>
 ) -> Result<()> {
     let multisig = &ctx.accounts.multisig;
     let proposal = &mut ctx.accounts.proposal;
     let signature = &mut ctx.accounts.signature;
-    let guardian = &ctx.accounts.guardian;
+    let guardian = &mut ctx.accounts.guardian;
     let clock = &ctx.accounts.clock;
     
-    msg!("Bắt đầu phê duyệt đề xuất với ID: {}", proposal_id);
-    msg!("Địa chỉ multisig: {}", multisig.key());
-    msg!("Guardian ID: {}", guardian_id);
-    msg!("Timestamp: {}", timestamp);
+    debug_msg!("Bắt đầu phê duyệt đề xuất với ID: {}", proposal_id);
+    debug_msg!("Địa chỉ multisig: {}", multisig.key());
+    debug_msg!("Guardian ID: {}", guardian_id);
     
     require!(
         *multisig.to_account_info().owner == crate::ID,
@@ -211,74 +412,25 @@ pub fn approve_proposal(
     );
     
     require!(
-        timestamp <= clock.unix_timestamp + 60, 
-        WalletError::FutureTimestamp
+        !proposal.is_expired(clock.unix_timestamp),
+        WalletError::ProposalExpired
     );
     
+    // Guardian phê duyệt đúng nội dung đã xem, không phải chỉ proposal_id
     require!(
-        timestamp >= clock.unix_timestamp - 300,
-        WalletError::ExpiredTimestamp
+        proposal.contents_hash == contents_hash,
+        WalletError::ProposalContentsMismatch
     );
     
-    if let Some(webauthn_pubkey) = guardian.webauthn_pubkey {
-        msg!("Guardian có WebAuthn public key: {}", to_hex(&webauthn_pubkey));
-        
-        let instruction_sysvar = &ctx.accounts.instruction_sysvar;
-        require!(
-            !instruction_sysvar.data_is_empty(),
-            WalletError::InstructionMissing
-        );
-        
-        let secp_ix = load_instruction_at_checked(0, instruction_sysvar)?;
-        
-        let secp256r1_verify_id = Pubkey::from_str("Secp256r1SigVerify1111111111111111111111111").unwrap();
-        require!(
-            secp_ix.program_id == secp256r1_verify_id,
-            WalletError::InvalidSignatureVerification
-        );
-        
-        let pk_in_ix = extract_public_key_from_secp_instruction(&secp_ix.data)?;
-        
-        msg!("Public key từ instruction: {}", to_hex(&pk_in_ix));
-        
-        require!(
-            pk_in_ix == webauthn_pubkey,
-            WalletError::PublicKeyMismatch
-        );
-        
-        let standardized_pubkey = standardize_pubkey(&webauthn_pubkey);
-        msg!("Standardized public key: {}", to_hex(&standardized_pubkey));
-        
-        let pubkey_hash = hash(&standardized_pubkey).to_bytes();
-        let pubkey_hash_hex = to_hex(&pubkey_hash[0..6]);
-        msg!("Public key hash after standardization: {}", pubkey_hash_hex);
-        
-        let expected_message = format!(
-            "approve:proposal_{},guardian_{},timestamp:{},pubkey:{}",
-            proposal_id,
-            guardian_id,
-            timestamp,
-            pubkey_hash_hex
-        );
-        
-        msg!("Expected message: {}", expected_message);
-        msg!("Received message length: {}", message.len());
-        msg!("Received message: {}", String::from_utf8_lossy(&message));
-        
-        if expected_message.as_bytes().len() == message.len() {
-            for (i, (exp, rec)) in expected_message.as_bytes().iter().zip(message.iter()).enumerate() {
-                if exp != rec {
-                    msg!("Khác biệt tại vị trí [{}]: Expected {} ({}), Received {} ({})", 
-                        i, exp, char::from(*exp), rec, char::from(*rec));
-                }
-            }
-        }
-        
-        require!(
-            message == expected_message.as_bytes(),
-            WalletError::MessageMismatch
-        );
-    }
+    verify_guardian_auth(
+        guardian,
+        multisig,
+        &ctx.accounts.instruction_sysvar,
+        ctx.accounts.guardian_signer.as_ref().map(|signer| signer.key()),
+        assertion.as_ref(),
+        &format!("approve:proposal_{},contents_{}", proposal_id, to_hex(&proposal.contents_hash)),
+        proposal.key()
+    )?;
     
     signature.proposal = proposal.key();
     signature.guardian_id = guardian_id;
@@ -286,36 +438,32 @@ pub fn approve_proposal(
     signature.bump = ctx.bumps.signature;
     
     proposal.signatures_count += 1;
+    proposal.approval_weight = proposal.approval_weight
+        .checked_add(guardian.weight as u16)
+        .ok_or(WalletError::ArithmeticOverflow)?;
+    
+    emit!(ProposalApproved {
+        multisig: proposal.multisig,
+        proposal_id,
+        guardian_id,
+        signatures_count: proposal.signatures_count,
+        required_signatures: proposal.required_signatures,
+        approval_weight: proposal.approval_weight,
+        required_weight: proposal.required_weight,
+    });
     
-    msg!("Guardian {} đã phê duyệt đề xuất {}", guardian_id, proposal_id);
+    debug_msg!("Guardian {} đã phê duyệt đề xuất {}", guardian_id, proposal_id);
     
     Ok(())
 }
 
 
-fn extract_public_key_from_secp_instruction(data: &[u8]) -> Result<[u8; 33]> {
-    if data.len() < 16 {
-        return Err(WalletError::InvalidInstructionData.into());
-    }
-    
-    let num_signatures = data[0] as usize;
-    if num_signatures != 1 {
-        return Err(WalletError::InvalidSignatureCount.into());
-    }
-    
-    let public_key_offset = u16::from_le_bytes([data[6], data[7]]) as usize;
-    
-    let mut pk = [0u8; 33];
-    if data.len() < public_key_offset + 33 {
-        return Err(WalletError::InvalidInstructionData.into());
-    }
-    
-    pk.copy_from_slice(&data[public_key_offset..public_key_offset + 33]);
-    Ok(pk)
+pub fn pubkey_hash_hex(webauthn_pubkey: &[u8; 33]) -> String {
+    let pubkey_hash = hash(webauthn_pubkey).to_bytes();
+    to_hex(&pubkey_hash[0..6])
 }
 
-
-fn to_hex(bytes: &[u8]) -> String {
+pub fn to_hex(bytes: &[u8]) -> String {
     let mut result = String::with_capacity(bytes.len() * 2);
     for byte in bytes {
         let hex = format!("{:02x}", byte);
@@ -325,8 +473,8 @@ fn to_hex(bytes: &[u8]) -> String {
 }
 
 #[derive(Accounts)]
-#[instruction(proposal_id: u64)]
-pub struct ExecuteProposal<'info> {
+#[instruction(proposal_id: u64, guardian_id: u64)]
+pub struct RejectProposal<'info> {
     #[account(mut)]
     pub multisig: Account<'info, MultiSigWallet>,
     
@@ -339,224 +487,169 @@ pub struct ExecuteProposal<'info> {
         ],
         bump = proposal.bump,
         constraint = proposal.status == ProposalStatus::Pending @ WalletError::InvalidOperation,
-        constraint = proposal.signatures_count >= proposal.required_signatures @ WalletError::InvalidOperation,
         constraint = *multisig.to_account_info().key == proposal.multisig @ WalletError::MultisigMismatch
     )]
     pub proposal: Account<'info, TransactionProposal>,
     
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    
-    #[account(mut)]
-    pub destination: AccountInfo<'info>,
-    
-    pub clock: Sysvar<'info, Clock>,
-    pub system_program: Program<'info, System>,
-}
-
-// Tạo một cấu trúc riêng cho giao dịch token
-#[derive(Accounts)]
-#[instruction(proposal_id: u64)]
-pub struct ExecuteTokenProposal<'info> {
-    #[account(mut)]
-    pub multisig: Account<'info, MultiSigWallet>,
-    
     #[account(
-        mut,
+        init,
+        payer = payer,
+        space = 8 + 
+                32 +  
+                8 +  
+                8 +  
+                1,    
         seeds = [
-            b"proposal".as_ref(), 
-            multisig.key().as_ref(), 
-            &proposal_id.to_le_bytes()
+            b"rejection".as_ref(),
+            proposal.key().as_ref(),
+            &guardian_id.to_le_bytes()
         ],
-        bump = proposal.bump,
-        constraint = proposal.status == ProposalStatus::Pending @ WalletError::InvalidOperation,
-        constraint = proposal.signatures_count >= proposal.required_signatures @ WalletError::InvalidOperation,
-        constraint = *multisig.to_account_info().key == proposal.multisig @ WalletError::MultisigMismatch,
-        constraint = proposal.action == "transfer_token" @ WalletError::InvalidOperation
+        bump
     )]
-    pub proposal: Account<'info, TransactionProposal>,
+    pub rejection: Account<'info, ProposalRejection>,
     
-    #[account(mut)]
-    pub payer: Signer<'info>,
+    /// CHECK: PDA chữ ký của cùng guardian, phải chưa được tạo để một guardian
+    /// không vừa phê duyệt vừa từ chối một đề xuất
+    #[account(
+        seeds = [
+            b"signature".as_ref(),
+            proposal.key().as_ref(),
+            &guardian_id.to_le_bytes()
+        ],
+        bump,
+        constraint = existing_signature.data_is_empty() @ WalletError::AlreadyApproved
+    )]
+    pub existing_signature: UncheckedAccount<'info>,
     
-    #[account(mut)]
-    pub destination: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &guardian_id.to_le_bytes()],
+        bump = guardian.bump,
+        constraint = guardian.is_active @ WalletError::InactiveGuardian,
+        constraint = guardian.has_permission(Guardian::PERMISSION_APPROVE) @ WalletError::MissingPermission
+    )]
+    pub guardian: Account<'info, Guardian>,
     
-    #[account(mut)]
-    pub from_token_account: Account<'info, TokenAccount>,
+    pub guardian_signer: Option<Signer<'info>>,
     
     #[account(mut)]
-    pub to_token_account: Account<'info, TokenAccount>,
+    pub payer: Signer<'info>,
     
-    pub token_program: Program<'info, Token>,
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instruction_sysvar: AccountInfo<'info>,
     
     pub clock: Sysvar<'info, Clock>,
     pub system_program: Program<'info, System>,
 }
 
-pub fn execute_proposal(
-    ctx: Context<ExecuteProposal>,
-    proposal_id: u64
+pub fn reject_proposal(
+    ctx: Context<RejectProposal>,
+    proposal_id: u64,
+    guardian_id: u64,
+    assertion: Option<WebAuthnAssertion>
 ) -> Result<()> {
-    let multisig_credential_id = ctx.accounts.multisig.credential_id.clone();
-    let multisig_bump = ctx.accounts.multisig.bump;
-    
-    let multisig = &mut ctx.accounts.multisig;
+    let multisig = &ctx.accounts.multisig;
     let proposal = &mut ctx.accounts.proposal;
+    let rejection = &mut ctx.accounts.rejection;
+    let guardian = &mut ctx.accounts.guardian;
     let clock = &ctx.accounts.clock;
     
+    debug_msg!("Bắt đầu từ chối đề xuất với ID: {}", proposal_id);
+    debug_msg!("Địa chỉ multisig: {}", multisig.key());
+    debug_msg!("Guardian ID: {}", guardian_id);
+    
     require!(
         *multisig.to_account_info().owner == crate::ID,
         WalletError::InvalidOwner
     );
     
-    require!(
-        proposal.signatures_count >= proposal.required_signatures,
-        WalletError::InvalidOperation
-    );
-    
-    msg!("Thực thi đề xuất chuyển SOL với ID: {}", proposal_id);
-    msg!("Địa chỉ multisig: {}", multisig.key());
-    msg!("Số chữ ký hiện tại: {}/{}", proposal.signatures_count, proposal.required_signatures);
-    
-    // Chỉ xử lý đề xuất chuyển SOL trong hàm này
-    require!(
-        proposal.action == "transfer",
-        WalletError::UnsupportedAction
-    );
-    
-    let destination = ctx.accounts.destination.key();
-    let params_destination = proposal.params.destination.ok_or(WalletError::InvalidOperation)?;
-    
-    require!(
-        params_destination == destination,
-        WalletError::InvalidOperation
-    );
-    
-    let amount = proposal.params.amount.ok_or(WalletError::InvalidOperation)?;
+    verify_guardian_auth(
+        guardian,
+        multisig,
+        &ctx.accounts.instruction_sysvar,
+        ctx.accounts.guardian_signer.as_ref().map(|signer| signer.key()),
+        assertion.as_ref(),
+        &format!("reject:proposal_{},contents_{}", proposal_id, to_hex(&proposal.contents_hash)),
+        proposal.key()
+    )?;
     
-    let multisig_info = multisig.to_account_info();
-    let credential_id_bytes = process_credential_id_seed(&multisig_credential_id);
-    let _seeds = &[
-        b"multisig".as_ref(),
-        &credential_id_bytes,
-        &[multisig_bump]
-    ];
+    rejection.proposal = proposal.key();
+    rejection.guardian_id = guardian_id;
+    rejection.rejection_time = clock.unix_timestamp;
+    rejection.bump = ctx.bumps.rejection;
     
-    let dest_starting_lamports = ctx.accounts.destination.lamports();
-    **ctx.accounts.destination.lamports.borrow_mut() = dest_starting_lamports
-        .checked_add(amount)
-        .ok_or(WalletError::ArithmeticOverflow)?;
+    proposal.rejections_count += 1;
     
-    let multisig_starting_lamports = multisig_info.lamports();
-    **multisig_info.lamports.borrow_mut() = multisig_starting_lamports
-        .checked_sub(amount)
-        .ok_or(WalletError::InsufficientFunds)?;
+    debug_msg!("Guardian {} đã từ chối đề xuất {}", guardian_id, proposal_id);
+    debug_msg!("Số lượt từ chối hiện tại: {}/{}", proposal.rejections_count, proposal.required_rejections);
     
-    msg!("Đã chuyển {} SOL đến {}", amount as f64 / 1_000_000_000.0, destination);
+    let finalized = proposal.rejections_count >= proposal.required_rejections;
+    if finalized {
+        proposal.status = ProposalStatus::Rejected;
+        debug_msg!("Đề xuất {} đã bị từ chối", proposal_id);
+    }
     
-    // Cập nhật trạng thái đề xuất
-    proposal.status = ProposalStatus::Executed;
-    proposal.executed_at = Some(clock.unix_timestamp);
+    emit!(ProposalRejected {
+        multisig: proposal.multisig,
+        proposal_id,
+        guardian_id,
+        rejections_count: proposal.rejections_count,
+        required_rejections: proposal.required_rejections,
+        finalized,
+    });
     
-    multisig.transaction_nonce += 1;
-    multisig.last_transaction_timestamp = clock.unix_timestamp;
+    Ok(())
+} 
+
+#[derive(Accounts)]
+#[instruction(proposal_id: u64)]
+pub struct ExpireProposal<'info> {
+    pub multisig: Account<'info, MultiSigWallet>,
     
-    msg!("Đã thực thi đề xuất {} thành công", proposal_id);
+    #[account(
+        mut,
+        seeds = [
+            b"proposal".as_ref(), 
+            multisig.key().as_ref(), 
+            &proposal_id.to_le_bytes()
+        ],
+        bump = proposal.bump,
+        constraint = proposal.status == ProposalStatus::Pending @ WalletError::InvalidOperation,
+        constraint = *multisig.to_account_info().key == proposal.multisig @ WalletError::MultisigMismatch
+    )]
+    pub proposal: Account<'info, TransactionProposal>,
     
-    Ok(())
+    pub clock: Sysvar<'info, Clock>,
 }
 
-// Thêm hàm mới để xử lý đề xuất chuyển token
-pub fn execute_token_proposal(
-    ctx: Context<ExecuteTokenProposal>,
+// Bất kỳ ai cũng có thể đánh dấu đề xuất đã quá hạn
+pub fn expire_proposal(
+    ctx: Context<ExpireProposal>,
     proposal_id: u64
 ) -> Result<()> {
-    let multisig_credential_id = ctx.accounts.multisig.credential_id.clone();
-    let multisig_bump = ctx.accounts.multisig.bump;
-    
-    let multisig = &mut ctx.accounts.multisig;
     let proposal = &mut ctx.accounts.proposal;
     let clock = &ctx.accounts.clock;
     
     require!(
-        *multisig.to_account_info().owner == crate::ID,
-        WalletError::InvalidOwner
-    );
-    
-    require!(
-        proposal.signatures_count >= proposal.required_signatures,
-        WalletError::InvalidOperation
+        proposal.is_expired(clock.unix_timestamp),
+        WalletError::ProposalNotExpired
     );
     
-    msg!("Thực thi đề xuất chuyển token với ID: {}", proposal_id);
-    msg!("Địa chỉ multisig: {}", multisig.key());
-    msg!("Số chữ ký hiện tại: {}/{}", proposal.signatures_count, proposal.required_signatures);
+    proposal.status = ProposalStatus::Expired;
     
-    // Chỉ xử lý đề xuất chuyển token trong hàm này
-    require!(
-        proposal.action == "transfer_token",
-        WalletError::UnsupportedAction
-    );
-    
-    let token_mint = proposal.params.token_mint.ok_or(WalletError::InvalidOperation)?;
-    let token_amount = proposal.params.token_amount.ok_or(WalletError::InvalidOperation)?;
+    emit!(ProposalExpired {
+        multisig: proposal.multisig,
+        proposal_id,
+    });
     
-    let from_token_account = &ctx.accounts.from_token_account;
-    let to_token_account = &ctx.accounts.to_token_account;
-    
-    require!(
-        from_token_account.mint == token_mint,
-        WalletError::InvalidOperation
-    );
-    
-    require!(
-        to_token_account.mint == token_mint,
-        WalletError::InvalidOperation
-    );
-    
-    let credential_id_bytes = process_credential_id_seed(&multisig_credential_id);
-    let seeds = &[
-        b"multisig".as_ref(),
-        &credential_id_bytes,
-        &[multisig_bump]
-    ];
-    let signer_seeds = &[&seeds[..]];
-    
-    token::transfer(
-        CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            SplTransfer {
-                from: from_token_account.to_account_info(),
-                to: to_token_account.to_account_info(),
-                authority: multisig.to_account_info(),
-            },
-            signer_seeds,
-        ),
-        token_amount
-    )?;
-    
-    msg!("Đã chuyển {} token ({}) từ multisig đến {}", 
-        token_amount, 
-        token_mint.to_string(), 
-        ctx.accounts.destination.key());
-    
-    // Cập nhật trạng thái đề xuất
-    proposal.status = ProposalStatus::Executed;
-    proposal.executed_at = Some(clock.unix_timestamp);
-    
-    multisig.transaction_nonce += 1;
-    multisig.last_transaction_timestamp = clock.unix_timestamp;
-    
-    msg!("Đã thực thi đề xuất {} thành công", proposal_id);
+    debug_msg!("Đề xuất {} đã hết hạn", proposal_id);
     
     Ok(())
 }
 
 #[derive(Accounts)]
-#[instruction(proposal_id: u64, guardian_id: u64, timestamp: i64)]
-pub struct RejectProposal<'info> {
-    #[account(mut)]
+#[instruction(proposal_id: u64)]
+pub struct CloseProposal<'info> {
     pub multisig: Account<'info, MultiSigWallet>,
     
     #[account(
@@ -567,118 +660,77 @@ pub struct RejectProposal<'info> {
             &proposal_id.to_le_bytes()
         ],
         bump = proposal.bump,
-        constraint = proposal.status == ProposalStatus::Pending @ WalletError::InvalidOperation,
+        constraint = proposal.status != ProposalStatus::Pending @ WalletError::ProposalNotFinalized,
+        constraint = proposal.status != ProposalStatus::Executing @ WalletError::ProposalNotFinalized,
         constraint = *multisig.to_account_info().key == proposal.multisig @ WalletError::MultisigMismatch
     )]
     pub proposal: Account<'info, TransactionProposal>,
     
-    #[account(
-        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &guardian_id.to_le_bytes()],
-        bump = guardian.bump,
-        constraint = guardian.is_active @ WalletError::InactiveGuardian
-    )]
-    pub guardian: Account<'info, Guardian>,
-    
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    
-    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
-    pub instruction_sysvar: AccountInfo<'info>,
-    
-    pub clock: Sysvar<'info, Clock>,
+    /// CHECK: Người đã trả rent khi tạo đề xuất, được kiểm tra qua address
+    #[account(mut, address = proposal.payer @ WalletError::InvalidOperation)]
+    pub rent_receiver: AccountInfo<'info>,
 }
 
-pub fn reject_proposal(
-    ctx: Context<RejectProposal>,
-    proposal_id: u64,
-    guardian_id: u64,
-    timestamp: i64,
-    message: Vec<u8>
+// remaining_accounts: một phần ProposalSignature và ProposalRejection của đề xuất.
+// Có thể gọi nhiều lần khi không chứa hết trong một giao dịch, đề xuất chỉ bị đóng
+// khi mọi PDA chữ ký/từ chối đã được đóng
+pub fn close_proposal<'info>(
+    ctx: Context<'_, '_, 'info, 'info, CloseProposal<'info>>,
+    proposal_id: u64
 ) -> Result<()> {
-    let multisig = &ctx.accounts.multisig;
-    let proposal = &mut ctx.accounts.proposal;
-    let guardian = &ctx.accounts.guardian;
-    let clock = &ctx.accounts.clock;
-    
-    msg!("Bắt đầu từ chối đề xuất với ID: {}", proposal_id);
-    msg!("Địa chỉ multisig: {}", multisig.key());
-    msg!("Guardian ID: {}", guardian_id);
-    msg!("Timestamp: {}", timestamp);
-    
-    require!(
-        *multisig.to_account_info().owner == crate::ID,
-        WalletError::InvalidOwner
-    );
-    
-    require!(
-        timestamp <= clock.unix_timestamp + 60, 
-        WalletError::FutureTimestamp
-    );
-    
-    require!(
-        timestamp >= clock.unix_timestamp - 300,
-        WalletError::ExpiredTimestamp
-    );
-    
-    if let Some(webauthn_pubkey) = guardian.webauthn_pubkey {
-        msg!("Guardian có WebAuthn public key: {}", to_hex(&webauthn_pubkey));
-        
-        let instruction_sysvar = &ctx.accounts.instruction_sysvar;
-        require!(
-            !instruction_sysvar.data_is_empty(),
-            WalletError::InstructionMissing
-        );
-        
-        let secp_ix = load_instruction_at_checked(0, instruction_sysvar)?;
-        
-        let secp256r1_verify_id = Pubkey::from_str("Secp256r1SigVerify1111111111111111111111111").unwrap();
-        require!(
-            secp_ix.program_id == secp256r1_verify_id,
-            WalletError::InvalidSignatureVerification
-        );
-        
-        let pk_in_ix = extract_public_key_from_secp_instruction(&secp_ix.data)?;
-        
-        msg!("Public key từ instruction: {}", to_hex(&pk_in_ix));
-        
-        require!(
-            pk_in_ix == webauthn_pubkey,
-            WalletError::PublicKeyMismatch
-        );
-        
-        let pubkey_hash = hash(&webauthn_pubkey).to_bytes();
-        let pubkey_hash_hex = to_hex(&pubkey_hash[0..6]);
-        
-        let expected_message = format!(
-            "reject:proposal_{},guardian_{},timestamp:{},pubkey:{}",
-            proposal_id,
-            guardian_id,
-            timestamp,
-            pubkey_hash_hex
-        );
-        
-        msg!("Expected message: {}", expected_message);
-        msg!("Received message length: {}", message.len());
-        msg!("Received message: {}", String::from_utf8_lossy(&message));
-        
-        if expected_message.as_bytes().len() == message.len() {
-            for (i, (exp, rec)) in expected_message.as_bytes().iter().zip(message.iter()).enumerate() {
-                if exp != rec {
-                    msg!("Khác biệt tại vị trí [{}]: Expected {} ({}), Received {} ({})", 
-                        i, exp, char::from(*exp), rec, char::from(*rec));
-                }
-            }
+    let proposal = &ctx.accounts.proposal;
+    let proposal_key = proposal.key();
+    let rent_receiver = ctx.accounts.rent_receiver.to_account_info();
+    
+    let mut closed_signatures: u8 = 0;
+    let mut closed_rejections: u8 = 0;
+    
+    for account_info in ctx.remaining_accounts.iter() {
+        if let Ok(signature) = Account::<ProposalSignature>::try_from(account_info) {
+            require!(signature.proposal == proposal_key, WalletError::InvalidProposalAccount);
+            let expected_key = Pubkey::create_program_address(
+                &[
+                    b"signature".as_ref(),
+                    proposal_key.as_ref(),
+                    &signature.guardian_id.to_le_bytes(),
+                    &[signature.bump],
+                ],
+                &crate::ID
+            ).map_err(|_| WalletError::InvalidProposalAccount)?;
+            require!(expected_key == account_info.key(), WalletError::InvalidProposalAccount);
+            
+            signature.close(rent_receiver.clone())?;
+            closed_signatures += 1;
+        } else if let Ok(rejection) = Account::<ProposalRejection>::try_from(account_info) {
+            require!(rejection.proposal == proposal_key, WalletError::InvalidProposalAccount);
+            let expected_key = Pubkey::create_program_address(
+                &[
+                    b"rejection".as_ref(),
+                    proposal_key.as_ref(),
+                    &rejection.guardian_id.to_le_bytes(),
+                    &[rejection.bump],
+                ],
+                &crate::ID
+            ).map_err(|_| WalletError::InvalidProposalAccount)?;
+            require!(expected_key == account_info.key(), WalletError::InvalidProposalAccount);
+            
+            rejection.close(rent_receiver.clone())?;
+            closed_rejections += 1;
+        } else {
+            return Err(WalletError::InvalidProposalAccount.into());
         }
-        
-        require!(
-            message == expected_message.as_bytes(),
-            WalletError::MessageMismatch
-        );
     }
     
-    proposal.status = ProposalStatus::Rejected;
+    let proposal = &mut ctx.accounts.proposal;
+    let all_closed = proposal.record_closed(closed_signatures, closed_rejections)?;
+    
+    debug_msg!("Đã đóng {} chữ ký và {} lượt từ chối của đề xuất {}", closed_signatures, closed_rejections, proposal_id);
     
-    msg!("Guardian {} đã từ chối đề xuất {}", guardian_id, proposal_id);
+    // Không để sót PDA nào của đề xuất trước khi đóng đề xuất
+    if all_closed {
+        proposal.close(ctx.accounts.rent_receiver.to_account_info())?;
+        debug_msg!("Đã đóng đề xuất {}", proposal_id);
+    }
     
     Ok(())
-} 
\ No newline at end of file
+}
@@ -0,0 +1,463 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+use crate::state::*;
+use crate::errors::*;
+use crate::events::*;
+use crate::instructions::wallet::{multisig_seed, MULTISIG_SEED_VERSION};
+use crate::instructions::proposal::pubkey_hash_hex;
+use crate::instructions::guardian::verify_guardian_auth;
+use crate::webauthn::WebAuthnAssertion;
+
+#[derive(Accounts)]
+#[instruction(old_guardian_id: u64, new_guardian_id: u64, initiator_guardian_id: u64)]
+pub struct InitiateRecovery<'info> {
+    #[account(
+        seeds = [b"multisig".as_ref(), &[MULTISIG_SEED_VERSION], &multisig_seed(&multisig.credential_id)],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, MultiSigWallet>,
+
+    #[account(
+        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &old_guardian_id.to_le_bytes()],
+        bump = old_guardian.bump,
+        constraint = old_guardian.is_owner @ WalletError::InvalidOperation,
+        constraint = old_guardian.is_active @ WalletError::InactiveGuardian
+    )]
+    pub old_guardian: Account<'info, Guardian>,
+
+    #[account(
+        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &new_guardian_id.to_le_bytes()],
+        bump = new_guardian.bump,
+        constraint = new_guardian.is_active @ WalletError::InactiveGuardian
+    )]
+    pub new_guardian: Account<'info, Guardian>,
+
+    // Chỉ guardian có quyền khôi phục mới được tạo yêu cầu, và phải tự xác thực
+    #[account(
+        mut,
+        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &initiator_guardian_id.to_le_bytes()],
+        bump = initiator_guardian.bump,
+        constraint = initiator_guardian.is_active @ WalletError::InactiveGuardian,
+        constraint = initiator_guardian.has_permission(Guardian::PERMISSION_INITIATE_RECOVERY) @ WalletError::MissingPermission
+    )]
+    pub initiator_guardian: Account<'info, Guardian>,
+
+    pub guardian_signer: Option<Signer<'info>>,
+
+    // Mỗi recovery_nonce chỉ có một yêu cầu đang chờ
+    #[account(
+        init,
+        payer = payer,
+        space = RecoveryRequest::SPACE,
+        seeds = [b"recovery".as_ref(), multisig.key().as_ref(), &multisig.recovery_nonce.to_le_bytes()],
+        bump
+    )]
+    pub recovery_request: Account<'info, RecoveryRequest>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: Sysvar instructions, được kiểm tra qua address
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instruction_sysvar: AccountInfo<'info>,
+
+    pub clock: Sysvar<'info, Clock>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn initiate_recovery(
+    ctx: Context<InitiateRecovery>,
+    old_guardian_id: u64,
+    new_guardian_id: u64,
+    recovery_hash_intermediate: [u8; 32],
+    new_webauthn_pubkey: [u8; 33],
+    assertion: Option<WebAuthnAssertion>,
+) -> Result<()> {
+    let multisig = &ctx.accounts.multisig;
+    let clock = &ctx.accounts.clock;
+
+    require!(old_guardian_id != new_guardian_id, WalletError::InvalidOperation);
+    require!(
+        ctx.accounts.old_guardian.recovery_hash == hash(&recovery_hash_intermediate).to_bytes(),
+        WalletError::InvalidRecoveryKey
+    );
+
+    verify_guardian_auth(
+        &mut ctx.accounts.initiator_guardian,
+        multisig,
+        &ctx.accounts.instruction_sysvar,
+        ctx.accounts.guardian_signer.as_ref().map(|signer| signer.key()),
+        assertion.as_ref(),
+        &format!(
+            "initiate_recovery:old_{},new_{},new_owner_{}",
+            old_guardian_id,
+            new_guardian_id,
+            pubkey_hash_hex(&new_webauthn_pubkey)
+        ),
+        ctx.accounts.recovery_request.key()
+    )?;
+
+    let request = &mut ctx.accounts.recovery_request;
+    request.multisig = multisig.key();
+    request.nonce = multisig.recovery_nonce;
+    request.old_guardian_id = old_guardian_id;
+    request.new_guardian_id = new_guardian_id;
+    request.new_webauthn_pubkey = new_webauthn_pubkey;
+    request.initiator = ctx.accounts.payer.key();
+    request.created_at = clock.unix_timestamp;
+    request.executable_at = clock.unix_timestamp
+        .checked_add(multisig.recovery_delay)
+        .ok_or(WalletError::ArithmeticOverflow)?;
+    request.expires_at = request.executable_at
+        .checked_add(RecoveryRequest::EXPIRY_WINDOW)
+        .ok_or(WalletError::ArithmeticOverflow)?;
+    request.required_approvals = multisig.recovery_threshold;
+    request.approvers = Vec::new();
+    request.bump = ctx.bumps.recovery_request;
+
+    emit!(RecoveryInitiated {
+        multisig: multisig.key(),
+        nonce: request.nonce,
+        old_guardian_id,
+        new_guardian_id,
+        executable_at: request.executable_at,
+        expires_at: request.expires_at,
+    });
+
+    debug_msg!("Đã tạo yêu cầu khôi phục {} cho guardian {}", request.nonce, new_guardian_id);
+    debug_msg!("Có thể hoàn tất sau: {}", request.executable_at);
+    debug_msg!("Cần {} guardian phê duyệt", request.required_approvals);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(guardian_id: u64)]
+pub struct ApproveRecovery<'info> {
+    #[account(
+        seeds = [b"multisig".as_ref(), &[MULTISIG_SEED_VERSION], &multisig_seed(&multisig.credential_id)],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, MultiSigWallet>,
+
+    #[account(
+        mut,
+        seeds = [b"recovery".as_ref(), multisig.key().as_ref(), &multisig.recovery_nonce.to_le_bytes()],
+        bump = recovery_request.bump
+    )]
+    pub recovery_request: Account<'info, RecoveryRequest>,
+
+    // Owner đang bị thay thế và guardian sẽ nhận quyền owner không được tự phê duyệt; chỉ guardian có quyền khôi phục
+    // mới được phê duyệt, để guardian chỉ có quyền đề xuất không tham gia khôi phục
+    #[account(
+        mut,
+        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &guardian_id.to_le_bytes()],
+        bump = guardian.bump,
+        constraint = guardian.is_active @ WalletError::InactiveGuardian,
+        constraint = guardian.has_permission(Guardian::PERMISSION_INITIATE_RECOVERY) @ WalletError::MissingPermission,
+        constraint = guardian_id != recovery_request.old_guardian_id @ WalletError::InvalidOperation,
+        constraint = guardian_id != recovery_request.new_guardian_id @ WalletError::InvalidOperation
+    )]
+    pub guardian: Account<'info, Guardian>,
+
+    pub guardian_signer: Option<Signer<'info>>,
+
+    /// CHECK: Sysvar instructions, được kiểm tra qua address
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instruction_sysvar: AccountInfo<'info>,
+
+    pub clock: Sysvar<'info, Clock>,
+}
+
+/// Guardian xác nhận khoá WebAuthn mới của owner. Message ký gồm cả hash của khoá mới
+/// để một phê duyệt không thể bị dùng lại cho yêu cầu khác.
+pub fn approve_recovery(
+    ctx: Context<ApproveRecovery>,
+    guardian_id: u64,
+    assertion: Option<WebAuthnAssertion>,
+) -> Result<()> {
+    let request = &mut ctx.accounts.recovery_request;
+
+    require!(
+        !request.is_expired(ctx.accounts.clock.unix_timestamp),
+        WalletError::RecoveryExpired
+    );
+    require!(
+        !request.approvers.contains(&guardian_id),
+        WalletError::AlreadyApproved
+    );
+    require!(
+        request.approvers.len() < MAX_GUARDIANS,
+        WalletError::LimitExceeded
+    );
+
+    verify_guardian_auth(
+        &mut ctx.accounts.guardian,
+        &ctx.accounts.multisig,
+        &ctx.accounts.instruction_sysvar,
+        ctx.accounts.guardian_signer.as_ref().map(|signer| signer.key()),
+        assertion.as_ref(),
+        &format!(
+            "approve_recovery:recovery_{},new_owner_{}",
+            request.nonce,
+            pubkey_hash_hex(&request.new_webauthn_pubkey)
+        ),
+        request.key()
+    )?;
+
+    request.approvers.push(guardian_id);
+
+    emit!(RecoveryApproved {
+        multisig: ctx.accounts.multisig.key(),
+        nonce: request.nonce,
+        guardian_id,
+        approvals: request.approvers.len() as u8,
+        required_approvals: request.required_approvals,
+    });
+
+    debug_msg!("Guardian {} đã phê duyệt yêu cầu khôi phục {}", guardian_id, request.nonce);
+    debug_msg!("Số phê duyệt hiện tại: {}/{}", request.approvers.len(), request.required_approvals);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(owner_guardian_id: u64)]
+pub struct CancelRecovery<'info> {
+    #[account(
+        mut,
+        seeds = [b"multisig".as_ref(), &[MULTISIG_SEED_VERSION], &multisig_seed(&multisig.credential_id)],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, MultiSigWallet>,
+
+    #[account(
+        mut,
+        seeds = [b"recovery".as_ref(), multisig.key().as_ref(), &multisig.recovery_nonce.to_le_bytes()],
+        bump = recovery_request.bump,
+        close = initiator
+    )]
+    pub recovery_request: Account<'info, RecoveryRequest>,
+
+    /// CHECK: Người tạo yêu cầu, nhận lại rent
+    #[account(mut, address = recovery_request.initiator @ WalletError::InvalidOperation)]
+    pub initiator: AccountInfo<'info>,
+
+    /// Owner hiện tại phủ quyết yêu cầu trong thời gian chờ
+    #[account(
+        mut,
+        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &owner_guardian_id.to_le_bytes()],
+        bump = owner_guardian.bump,
+        constraint = owner_guardian.is_owner @ WalletError::InvalidOperation,
+        constraint = owner_guardian.is_active @ WalletError::InactiveGuardian
+    )]
+    pub owner_guardian: Account<'info, Guardian>,
+
+    pub guardian_signer: Option<Signer<'info>>,
+
+    /// CHECK: Sysvar instructions, được kiểm tra qua address
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instruction_sysvar: AccountInfo<'info>,
+
+    pub clock: Sysvar<'info, Clock>,
+}
+
+pub fn cancel_recovery(
+    ctx: Context<CancelRecovery>,
+    assertion: Option<WebAuthnAssertion>,
+) -> Result<()> {
+    let multisig = &mut ctx.accounts.multisig;
+    let nonce = ctx.accounts.recovery_request.nonce;
+
+    verify_guardian_auth(
+        &mut ctx.accounts.owner_guardian,
+        multisig,
+        &ctx.accounts.instruction_sysvar,
+        ctx.accounts.guardian_signer.as_ref().map(|signer| signer.key()),
+        assertion.as_ref(),
+        &format!("cancel_recovery:recovery_{}", nonce),
+        ctx.accounts.recovery_request.key()
+    )?;
+
+    multisig.recovery_nonce += 1;
+
+    emit!(RecoveryCancelled {
+        multisig: multisig.key(),
+        nonce,
+        owner_guardian_id: ctx.accounts.owner_guardian.guardian_id,
+    });
+
+    debug_msg!("Yêu cầu khôi phục {} đã bị huỷ", nonce);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct FinalizeRecovery<'info> {
+    #[account(
+        mut,
+        seeds = [b"multisig".as_ref(), &[MULTISIG_SEED_VERSION], &multisig_seed(&multisig.credential_id)],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, MultiSigWallet>,
+
+    #[account(
+        mut,
+        seeds = [b"recovery".as_ref(), multisig.key().as_ref(), &multisig.recovery_nonce.to_le_bytes()],
+        bump = recovery_request.bump,
+        close = initiator
+    )]
+    pub recovery_request: Account<'info, RecoveryRequest>,
+
+    /// CHECK: Người tạo yêu cầu, nhận lại rent
+    #[account(mut, address = recovery_request.initiator @ WalletError::InvalidOperation)]
+    pub initiator: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &recovery_request.old_guardian_id.to_le_bytes()],
+        bump = old_guardian.bump,
+        constraint = old_guardian.is_owner @ WalletError::InvalidRecovery,
+        constraint = old_guardian.is_active @ WalletError::InactiveGuardian
+    )]
+    pub old_guardian: Account<'info, Guardian>,
+
+    #[account(
+        mut,
+        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &recovery_request.new_guardian_id.to_le_bytes()],
+        bump = new_guardian.bump,
+        constraint = new_guardian.is_active @ WalletError::InactiveGuardian
+    )]
+    pub new_guardian: Account<'info, Guardian>,
+
+    pub clock: Sysvar<'info, Clock>,
+}
+
+/// Đếm các phê duyệt vẫn còn hiệu lực khi hoàn tất: guardian đã phê duyệt phải vẫn còn,
+/// đang hoạt động và còn quyền khôi phục
+fn count_valid_approvals<'info>(
+    multisig: &Pubkey,
+    request: &RecoveryRequest,
+    guardian_infos: &'info [AccountInfo<'info>],
+) -> Result<usize> {
+    let mut counted: Vec<u64> = Vec::with_capacity(request.approvers.len());
+
+    for guardian_info in guardian_infos {
+        let guardian = Account::<Guardian>::try_from(guardian_info)?;
+        require!(guardian.wallet == *multisig, WalletError::InvalidGuardian);
+
+        let guardian_id = guardian.guardian_id;
+        if request.approvers.contains(&guardian_id)
+            && !counted.contains(&guardian_id)
+            && guardian.is_active
+            && guardian.has_permission(Guardian::PERMISSION_INITIATE_RECOVERY)
+        {
+            counted.push(guardian_id);
+        }
+    }
+
+    Ok(counted.len())
+}
+
+// Bất kỳ ai cũng có thể hoàn tất yêu cầu khi đã hết thời gian chờ
+// remaining_accounts: tài khoản guardian của những người đã phê duyệt
+pub fn finalize_recovery<'info>(
+    ctx: Context<'_, '_, 'info, 'info, FinalizeRecovery<'info>>
+) -> Result<()> {
+    let multisig = &mut ctx.accounts.multisig;
+    let request = &ctx.accounts.recovery_request;
+
+    require!(
+        ctx.accounts.clock.unix_timestamp >= request.executable_at,
+        WalletError::RecoveryDelayNotElapsed
+    );
+    require!(
+        !request.is_expired(ctx.accounts.clock.unix_timestamp),
+        WalletError::RecoveryExpired
+    );
+    require!(
+        count_valid_approvals(&multisig.key(), request, ctx.remaining_accounts)?
+            >= request.required_approvals as usize,
+        WalletError::InsufficientRecoveryApprovals
+    );
+
+    let old_guardian = &mut ctx.accounts.old_guardian;
+    let new_guardian = &mut ctx.accounts.new_guardian;
+
+    old_guardian.is_owner = false;
+    multisig.owner_count -= 1;
+    match old_guardian.auth.without_webauthn_pubkey() {
+        Some(auth) => old_guardian.auth = auth,
+        None => {
+            multisig.on_guardian_deactivated_by_recovery(old_guardian);
+            old_guardian.is_active = false;
+        },
+    }
+
+    if !new_guardian.is_owner {
+        multisig.owner_count += 1;
+    }
+    new_guardian.is_owner = true;
+    new_guardian.permissions = Guardian::ALL_PERMISSIONS;
+    new_guardian.auth = new_guardian.auth.with_webauthn_pubkey(request.new_webauthn_pubkey);
+    new_guardian.sign_count = 0;
+
+    multisig.recovery_nonce += 1;
+
+    emit!(RecoveryCompleted {
+        multisig: multisig.key(),
+        nonce: request.nonce,
+        old_guardian_id: request.old_guardian_id,
+        new_guardian_id: request.new_guardian_id,
+    });
+
+    debug_msg!("Quyền truy cập đã được khôi phục thành công thông qua guardian");
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ExpireRecovery<'info> {
+    #[account(
+        mut,
+        seeds = [b"multisig".as_ref(), &[MULTISIG_SEED_VERSION], &multisig_seed(&multisig.credential_id)],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, MultiSigWallet>,
+
+    #[account(
+        mut,
+        seeds = [b"recovery".as_ref(), multisig.key().as_ref(), &multisig.recovery_nonce.to_le_bytes()],
+        bump = recovery_request.bump,
+        close = initiator
+    )]
+    pub recovery_request: Account<'info, RecoveryRequest>,
+
+    /// CHECK: Người tạo yêu cầu, nhận lại rent
+    #[account(mut, address = recovery_request.initiator @ WalletError::InvalidOperation)]
+    pub initiator: AccountInfo<'info>,
+
+    pub clock: Sysvar<'info, Clock>,
+}
+
+// Bất kỳ ai cũng có thể dọn yêu cầu khôi phục đã quá hạn, để yêu cầu giả mạo
+// hoặc không thể hoàn tất không chặn vĩnh viễn recovery_nonce hiện tại
+pub fn expire_recovery(ctx: Context<ExpireRecovery>) -> Result<()> {
+    let multisig = &mut ctx.accounts.multisig;
+    let nonce = ctx.accounts.recovery_request.nonce;
+
+    require!(
+        ctx.accounts.recovery_request.is_expired(ctx.accounts.clock.unix_timestamp),
+        WalletError::RecoveryNotExpired
+    );
+
+    multisig.recovery_nonce += 1;
+
+    emit!(RecoveryExpired {
+        multisig: multisig.key(),
+        nonce,
+    });
+
+    debug_msg!("Yêu cầu khôi phục {} đã hết hạn", nonce);
+
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use anchor_lang::prelude::*;
+use crate::state::*;
+use crate::errors::*;
+use crate::instructions::wallet::{multisig_seed, MULTISIG_SEED_VERSION};
+use crate::instructions::guardian::verify_guardian_auth;
+use crate::instructions::execute::{check_destination_policy, execute_action, ActionAccounts};
+use crate::webauthn::WebAuthnAssertion;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+
+#[derive(Accounts)]
+#[instruction(mint: Pubkey, amount: u64, owner_guardian_id: u64)]
+pub struct SpendWithinLimit<'info> {
+    #[account(
+        mut,
+        seeds = [b"multisig".as_ref(), &[MULTISIG_SEED_VERSION], &multisig_seed(&multisig.credential_id)],
+        bump = multisig.bump
+    )]
+    pub multisig: Account<'info, MultiSigWallet>,
+
+    #[account(
+        mut,
+        seeds = [b"spending_limit".as_ref(), multisig.key().as_ref(), mint.as_ref()],
+        bump = spending_limit.bump
+    )]
+    pub spending_limit: Account<'info, SpendingLimit>,
+
+    #[account(
+        mut,
+        seeds = [b"guardian".as_ref(), multisig.key().as_ref(), &owner_guardian_id.to_le_bytes()],
+        bump = owner_guardian.bump,
+        constraint = owner_guardian.is_owner @ WalletError::InvalidOperation,
+        constraint = owner_guardian.is_active @ WalletError::InactiveGuardian
+    )]
+    pub owner_guardian: Account<'info, Guardian>,
+
+    pub guardian_signer: Option<Signer<'info>>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: Được so khớp với đích đến của khoản chi
+    #[account(mut)]
+    pub destination: AccountInfo<'info>,
+
+    // Chỉ cần khi chi token
+    #[account(mut)]
+    pub from_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: ATA của đích đến, được kiểm tra (và tạo nếu cần) khi chuyển
+    #[account(mut)]
+    pub to_token_account: Option<AccountInfo<'info>>,
+
+    pub token_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+
+    // Chỉ cần khi ví giới hạn địa chỉ đích
+    #[account(
+        seeds = [b"address_book".as_ref(), multisig.key().as_ref()],
+        bump = address_book.bump
+    )]
+    pub address_book: Option<Account<'info, AddressBook>>,
+
+    /// CHECK: Sysvar instructions, được kiểm tra qua address
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instruction_sysvar: AccountInfo<'info>,
+
+    pub clock: Sysvar<'info, Clock>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Owner chuyển tiền trong hạn mức mà không cần đề xuất.
+/// `mint` = Pubkey::default() để chuyển SOL.
+pub fn spend_within_limit<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SpendWithinLimit<'info>>,
+    mint: Pubkey,
+    amount: u64,
+    assertion: Option<WebAuthnAssertion>,
+) -> Result<()> {
+    let clock = &ctx.accounts.clock;
+    let destination = ctx.accounts.destination.key();
+
+    require!(amount > 0, WalletError::InvalidOperation);
+
+    verify_guardian_auth(
+        &mut ctx.accounts.owner_guardian,
+        &ctx.accounts.multisig,
+        &ctx.accounts.instruction_sysvar,
+        ctx.accounts.guardian_signer.as_ref().map(|signer| signer.key()),
+        assertion.as_ref(),
+        &format!("spend:mint_{},amount_{},destination_{}", mint, amount, destination),
+        ctx.accounts.spending_limit.key()
+    )?;
+
+    let spending_limit = &mut ctx.accounts.spending_limit;
+    require!(
+        spending_limit.allows_destination(&destination),
+        WalletError::DestinationNotAllowed
+    );
+    spending_limit.record_spend(amount, clock.unix_timestamp)?;
+
+    let action = if mint == Pubkey::default() {
+        ProposalAction::TransferSol { amount, destination }
+    } else {
+        ProposalAction::TransferToken { mint, amount, destination, recipient_token_account: None }
+    };
+
+    // Owner ký một mình nên chỉ tính là một chữ ký với chính sách ElevatedThreshold
+    check_destination_policy(
+        &ctx.accounts.multisig,
+        &action,
+        1,
+        ctx.accounts.address_book.as_ref()
+    )?;
+
+    execute_action(
+        &action,
+        &mut ActionAccounts {
+            multisig: &mut ctx.accounts.multisig,
+            payer: ctx.accounts.payer.to_account_info(),
+            destination: Some(&ctx.accounts.destination),
+            from_token_account: ctx.accounts.from_token_account.as_ref(),
+            to_token_account: ctx.accounts.to_token_account.as_ref(),
+            mint: ctx.accounts.token_mint.as_ref(),
+            token_program: ctx.accounts.token_program.as_ref(),
+            associated_token_program: ctx.accounts.associated_token_program.as_ref(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+            remaining_accounts: ctx.remaining_accounts,
+        }
+    )?;
+
+    debug_msg!(
+        "Đã chi {} trong hạn mức, đã dùng {}/{} trong kỳ",
+        amount,
+        ctx.accounts.spending_limit.spent()?,
+        ctx.accounts.spending_limit.amount
+    );
+
+    Ok(())
+}
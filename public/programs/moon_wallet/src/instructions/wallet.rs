@@ -1,27 +1,30 @@
 use anchor_lang::prelude::*;
 use crate::state::*;
 use crate::errors::*;
+use crate::events::*;
+use anchor_lang::solana_program::hash::hash;
 
 #[derive(Accounts)]
-#[instruction(threshold: u8, credential_id: String)]
+#[instruction(threshold: u8, credential_id: String, rp_id: String, proposal_ttl: i64, recovery_delay: i64, recovery_threshold: u8)]
 pub struct InitializeMultisig<'info> {
     #[account(
         init,
         payer = fee_payer,
-        space = 8 + 
-               1 +  
-               1 +  
-               8 +  
-               1 +  
-               8 +  
-               8 +  
-               32 + 
-               4 + credential_id.len(), 
-        seeds = [b"multisig".as_ref(), &process_credential_id_seed(&credential_id)],
+        space = MultiSigWallet::space(credential_id.len()),
+        seeds = [b"multisig".as_ref(), &[MULTISIG_SEED_VERSION], &multisig_seed(&credential_id)],
         bump
     )]
     pub multisig: Account<'info, MultiSigWallet>,
     
+    #[account(
+        init,
+        payer = fee_payer,
+        space = GuardianRegistry::space(0),
+        seeds = [b"guardian_registry".as_ref(), multisig.key().as_ref()],
+        bump
+    )]
+    pub guardian_registry: Account<'info, GuardianRegistry>,
+    
     #[account(mut)]
     pub fee_payer: Signer<'info>,
     
@@ -32,65 +35,91 @@ pub fn initialize_multisig(
     ctx: Context<InitializeMultisig>,
     threshold: u8,
     credential_id: String,
+    rp_id: String,
+    proposal_ttl: i64,
+    recovery_delay: i64,
+    recovery_threshold: u8,
 ) -> Result<()> {
     let multisig = &mut ctx.accounts.multisig;
     
     require!(threshold > 0, WalletError::InvalidConfig);
-    require!(credential_id.len() > 0, WalletError::InvalidConfig);
+    require!(!credential_id.is_empty(), WalletError::InvalidConfig);
     require!(credential_id.len() <= 64, WalletError::NameTooLong);
+    require!(!rp_id.is_empty(), WalletError::InvalidConfig);
+    require!(proposal_ttl >= 0, WalletError::InvalidConfig);
+    require!(recovery_delay >= 0, WalletError::InvalidConfig);
+    // Ví mới chưa có guardian nên chỉ giới hạn theo số guardian tối đa
+    require!(
+        recovery_threshold > 0 && recovery_threshold <= DEFAULT_MAX_GUARDIANS,
+        WalletError::InvalidConfig
+    );
     
     multisig.threshold = threshold;
     multisig.guardian_count = 0;
+    multisig.active_guardian_count = 0;
+    multisig.owner_count = 0;
     multisig.recovery_nonce = 0;
     multisig.bump = ctx.bumps.multisig;
     multisig.transaction_nonce = 0;
     multisig.last_transaction_timestamp = 0;
     multisig.owner = ctx.accounts.fee_payer.key();
     multisig.credential_id = credential_id;
+    multisig.rp_id_hash = hash(rp_id.as_bytes()).to_bytes();
+    multisig.rejection_threshold = 0;
+    multisig.proposal_ttl = proposal_ttl;
+    multisig.recovery_delay = recovery_delay;
+    multisig.recovery_threshold = recovery_threshold;
+    multisig.destination_policy = DestinationPolicy::Unrestricted;
+    multisig.next_proposal_id = 0;
+    multisig.weight_threshold = 0;
+    multisig.active_weight = 0;
+    multisig.max_guardians = DEFAULT_MAX_GUARDIANS;
+    
+    let guardian_registry = &mut ctx.accounts.guardian_registry;
+    guardian_registry.multisig = multisig.key();
+    guardian_registry.guardian_ids = Vec::new();
+    guardian_registry.bump = ctx.bumps.guardian_registry;
+    
+    emit!(WalletInitialized {
+        multisig: multisig.key(),
+        owner: multisig.owner,
+        threshold,
+        credential_id: multisig.credential_id.clone(),
+    });
     
     Ok(())
 }
 
-pub fn process_credential_id_seed(credential_id: &str) -> [u8; 24] {
-    msg!("CONTRACT - process_credential_id_seed");
-    msg!("Input credential ID: {}", credential_id);
-    
+/// Phiên bản của cách sinh seed PDA multisig, nằm ngay sau b"multisig" trong seeds
+pub const MULTISIG_SEED_VERSION: u8 = 1;
+
+/// Seed PDA multisig: sha256(credential_id), không bị trùng như cách XOR cũ
+pub fn multisig_seed(credential_id: &str) -> [u8; 32] {
+    hash(credential_id.as_bytes()).to_bytes()
+}
+
+/// Seed cũ (XOR các byte vào 24 byte), chỉ còn dùng để tìm ví cần migrate.
+/// Hai credential ID khác nhau dài hơn 24 byte có thể cho ra cùng một seed.
+pub fn legacy_credential_id_seed(credential_id: &str) -> [u8; 24] {
     let credential_bytes = credential_id.as_bytes();
-    msg!("Credential bytes length: {}", credential_bytes.len());
-    
-    let bytes_hex = to_hex(credential_bytes);
-    msg!("Credential bytes (hex): {}", bytes_hex);
-    
     let mut result = [0u8; 24];
     
     if credential_bytes.len() > 24 {
-        msg!("Credential ID dài quá 24 bytes, thực hiện hash");
-        
-       
         for (i, byte) in credential_bytes.iter().enumerate() {
             result[i % 24] ^= *byte;
         }
-        
-        let result_hex = to_hex(&result);
-        msg!("Seed sau khi hash (hex): {}", result_hex);
     } else {
-       
         let len = credential_bytes.len();
         result[..len].copy_from_slice(credential_bytes);
-        
-        let result_hex = to_hex(&result);
-        msg!("Seed không hash (hex, padded): {}", result_hex);
     }
     
     result
 }
 
-fn to_hex(bytes: &[u8]) -> String {
-    let mut result = String::with_capacity(bytes.len() * 2);
-    for byte in bytes {
-        let hex = format!("{:02x}", byte);
-        result.push_str(&hex);
-    }
-    result
+/// Địa chỉ PDA cũ của ví có credential_id này
+pub fn legacy_multisig_address(credential_id: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"multisig".as_ref(), &legacy_credential_id_seed(credential_id)],
+        &crate::ID
+    )
 }
-
@@ -1,16 +1,40 @@
+// Module __private::__idl do #[program] sinh ra ở gốc crate (không nằm trong module chương trình)
+// vẫn gọi AccountInfo::realloc, nên chỉ bỏ qua cảnh báo ở gốc crate và bật lại cho mã tự viết
+#![allow(deprecated)]
+
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::pubkey::Pubkey;
-use state::wallet::ActionParams;
+use state::proposal::ProposalAction;
+use state::guardian::GuardianAuth;
+use webauthn::WebAuthnAssertion;
 
 // Cập nhật version số
 declare_id!("6Y3N5AQRQtviTrmRcf29yHWBu3ft7xEoj8kqmykDGvKP");
 
+/// msg! chỉ để debug, chỉ được in khi build với feature `debug-logs`
+macro_rules! debug_msg {
+    ($($arg:tt)*) => {
+        if cfg!(feature = "debug-logs") {
+            anchor_lang::prelude::msg!($($arg)*);
+        }
+    };
+}
+
+#[warn(deprecated)]
 mod state;
+#[warn(deprecated)]
 mod instructions;
+#[warn(deprecated)]
 mod errors;
+#[warn(deprecated)]
+mod events;
+#[warn(deprecated)]
+mod webauthn;
 
 use instructions::*;
 
+#[warn(deprecated)]
+#[allow(clippy::too_many_arguments)]
 #[program]
 pub mod moon_wallet_program {
     use super::*;
@@ -18,9 +42,21 @@ pub mod moon_wallet_program {
     pub fn initialize_multisig(
         ctx: Context<InitializeMultisig>, 
         threshold: u8,
-        credential_id: String
+        credential_id: String,
+        rp_id: String,
+        proposal_ttl: i64,
+        recovery_delay: i64,
+        recovery_threshold: u8
     ) -> Result<()> {
-        instructions::wallet::initialize_multisig(ctx, threshold, credential_id)
+        instructions::wallet::initialize_multisig(
+            ctx,
+            threshold,
+            credential_id,
+            rp_id,
+            proposal_ttl,
+            recovery_delay,
+            recovery_threshold
+        )
     }
 
     pub fn add_guardian(
@@ -29,48 +65,105 @@ pub mod moon_wallet_program {
         guardian_name: String,
         recovery_hash_intermediate: [u8; 32],
         is_owner: bool,
-        webauthn_pubkey: Option<[u8; 33]>
+        permissions: u8,
+        auth: GuardianAuth,
+        owner_guardian_id: u64,
+        assertion: Option<WebAuthnAssertion>
     ) -> Result<()> {
-        instructions::guardian::add_guardian(ctx, guardian_id, guardian_name, recovery_hash_intermediate, is_owner, webauthn_pubkey)
+        instructions::guardian::add_guardian(
+            ctx,
+            guardian_id,
+            guardian_name,
+            recovery_hash_intermediate,
+            is_owner,
+            permissions,
+            auth,
+            owner_guardian_id,
+            assertion
+        )
     }
 
     pub fn remove_guardian(
         ctx: Context<RemoveGuardian>,
         _guardian_id: u64,
-        _owner_guardian_id: u64
+        _owner_guardian_id: u64,
+        assertion: Option<WebAuthnAssertion>
     ) -> Result<()> {
-        instructions::guardian::remove_guardian(ctx)
+        instructions::guardian::remove_guardian(ctx, assertion)
     }
 
     pub fn update_guardian_status(
         ctx: Context<UpdateGuardianStatus>, 
         _guardian_id: u64,
         _owner_guardian_id: u64,
-        is_active: bool
+        is_active: bool,
+        assertion: Option<WebAuthnAssertion>
     ) -> Result<()> {
-        instructions::guardian::update_guardian_status(ctx, is_active)
+        instructions::guardian::update_guardian_status(ctx, is_active, assertion)
     }
 
-    pub fn recover_access_by_guardian(
-        ctx: Context<RecoverAccessByGuardian>,
-        _old_guardian_id: u64,
-        _new_guardian_id: u64,
+    pub fn initiate_recovery(
+        ctx: Context<InitiateRecovery>,
+        old_guardian_id: u64,
+        new_guardian_id: u64,
+        _initiator_guardian_id: u64,
         recovery_hash_intermediate: [u8; 32],
         new_webauthn_pubkey: [u8; 33],
+        assertion: Option<WebAuthnAssertion>
+    ) -> Result<()> {
+        instructions::recovery::initiate_recovery(
+            ctx,
+            old_guardian_id,
+            new_guardian_id,
+            recovery_hash_intermediate,
+            new_webauthn_pubkey,
+            assertion
+        )
+    }
+
+    pub fn approve_recovery(
+        ctx: Context<ApproveRecovery>,
+        guardian_id: u64,
+        assertion: Option<WebAuthnAssertion>
+    ) -> Result<()> {
+        instructions::recovery::approve_recovery(ctx, guardian_id, assertion)
+    }
+
+    pub fn cancel_recovery(
+        ctx: Context<CancelRecovery>,
+        _owner_guardian_id: u64,
+        assertion: Option<WebAuthnAssertion>
+    ) -> Result<()> {
+        instructions::recovery::cancel_recovery(ctx, assertion)
+    }
+
+    pub fn finalize_recovery<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FinalizeRecovery<'info>>
     ) -> Result<()> {
-        instructions::guardian::recover_access_by_guardian(ctx, recovery_hash_intermediate, new_webauthn_pubkey)
+        instructions::recovery::finalize_recovery(ctx)
+    }
+
+    pub fn expire_recovery(ctx: Context<ExpireRecovery>) -> Result<()> {
+        instructions::recovery::expire_recovery(ctx)
     }
 
     // Chức năng mới: Tạo đề xuất giao dịch
     pub fn create_proposal(
         ctx: Context<CreateProposal>,
-        proposal_id: u64,
         description: String,
         proposer_guardian_id: u64,
-        action: String,
-        params: ActionParams
+        actions: Vec<ProposalAction>,
+        expires_at: Option<i64>,
+        assertion: Option<WebAuthnAssertion>
     ) -> Result<()> {
-        instructions::proposal::create_proposal(ctx, proposal_id, description, proposer_guardian_id, action, params)
+        instructions::proposal::create_proposal(
+            ctx,
+            description,
+            proposer_guardian_id,
+            actions,
+            expires_at,
+            assertion
+        )
     }
     
     // Chức năng mới: Phê duyệt đề xuất giao dịch
@@ -78,26 +171,31 @@ pub mod moon_wallet_program {
         ctx: Context<ApproveProposal>,
         proposal_id: u64,
         guardian_id: u64,
-        timestamp: i64,
-        message: Vec<u8>
+        contents_hash: [u8; 32],
+        assertion: Option<WebAuthnAssertion>
     ) -> Result<()> {
-        instructions::proposal::approve_proposal(ctx, proposal_id, guardian_id, timestamp, message)
+        instructions::proposal::approve_proposal(ctx, proposal_id, guardian_id, contents_hash, assertion)
     }
     
     // Chức năng mới: Thực thi đề xuất giao dịch
-    pub fn execute_proposal(
-        ctx: Context<ExecuteProposal>,
-        proposal_id: u64
+    pub fn execute_proposal<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteProposal<'info>>,
+        proposal_id: u64,
+        _executor_guardian_id: u64,
+        max_actions: Option<u8>,
+        assertion: Option<WebAuthnAssertion>
     ) -> Result<()> {
-        instructions::proposal::execute_proposal(ctx, proposal_id)
+        instructions::execute::execute_proposal(ctx, proposal_id, max_actions, assertion)
     }
     
     // Chức năng mới: Thực thi đề xuất chuyển token
-    pub fn execute_token_proposal(
-        ctx: Context<ExecuteTokenProposal>,
-        proposal_id: u64
+    pub fn execute_token_proposal<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteTokenProposal<'info>>,
+        proposal_id: u64,
+        _executor_guardian_id: u64,
+        assertion: Option<WebAuthnAssertion>
     ) -> Result<()> {
-        instructions::proposal::execute_token_proposal(ctx, proposal_id)
+        instructions::execute::execute_token_proposal(ctx, proposal_id, assertion)
     }
     
     // Chức năng mới: Từ chối đề xuất giao dịch
@@ -105,10 +203,55 @@ pub mod moon_wallet_program {
         ctx: Context<RejectProposal>,
         proposal_id: u64,
         guardian_id: u64,
-        timestamp: i64,
-        message: Vec<u8>
+        assertion: Option<WebAuthnAssertion>
+    ) -> Result<()> {
+        instructions::proposal::reject_proposal(ctx, proposal_id, guardian_id, assertion)
+    }
+    
+    pub fn expire_proposal(
+        ctx: Context<ExpireProposal>,
+        proposal_id: u64
+    ) -> Result<()> {
+        instructions::proposal::expire_proposal(ctx, proposal_id)
+    }
+    
+    pub fn close_proposal<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CloseProposal<'info>>,
+        proposal_id: u64
+    ) -> Result<()> {
+        instructions::proposal::close_proposal(ctx, proposal_id)
+    }
+    
+    pub fn spend_within_limit<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SpendWithinLimit<'info>>,
+        mint: Pubkey,
+        amount: u64,
+        _owner_guardian_id: u64,
+        assertion: Option<WebAuthnAssertion>
+    ) -> Result<()> {
+        instructions::spending_limit::spend_within_limit(ctx, mint, amount, assertion)
+    }
+    
+    pub fn migrate_multisig(
+        ctx: Context<MigrateMultisig>,
+        rp_id: String,
+        recovery_delay: i64,
+        recovery_threshold: u8
+    ) -> Result<()> {
+        instructions::migrate::migrate_multisig(ctx, rp_id, recovery_delay, recovery_threshold)
+    }
+    
+    pub fn migrate_guardian(
+        ctx: Context<MigrateGuardian>,
+        guardian_id: u64
+    ) -> Result<()> {
+        instructions::migrate::migrate_guardian(ctx, guardian_id)
+    }
+    
+    pub fn migrate_token_account<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MigrateTokenAccount<'info>>
     ) -> Result<()> {
-        instructions::proposal::reject_proposal(ctx, proposal_id, guardian_id, timestamp, message)
+        instructions::migrate::migrate_token_account(ctx)
     }
 }
 
@@ -0,0 +1,29 @@
+use anchor_lang::prelude::*;
+
+/// Số địa chỉ tối đa trong sổ địa chỉ của một ví
+pub const MAX_ADDRESS_BOOK_ENTRIES: usize = 20;
+
+/// Sổ địa chỉ tin cậy của ví. Seeds: [b"address_book", multisig].
+#[account]
+pub struct AddressBook {
+    pub multisig: Pubkey,
+    pub entries: Vec<AddressBookEntry>,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub struct AddressBookEntry {
+    pub address: Pubkey,
+    pub label: String,
+}
+
+impl AddressBook {
+    pub const SPACE: usize = 8 +
+        32 +
+        4 + (32 + 4 + 32) * MAX_ADDRESS_BOOK_ENTRIES +
+        1;
+
+    pub fn contains(&self, address: &Pubkey) -> bool {
+        self.entries.iter().any(|entry| entry.address == *address)
+    }
+}
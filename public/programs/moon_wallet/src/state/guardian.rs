@@ -1,4 +1,5 @@
 use anchor_lang::prelude::*;
+use crate::errors::*;
 
 #[account]
 pub struct Guardian {
@@ -8,6 +9,133 @@ pub struct Guardian {
     pub is_active: bool,             
     pub recovery_hash: [u8; 32],     
     pub is_owner: bool,             
-    pub webauthn_pubkey: Option<[u8; 33]>,
+    pub permissions: u8,
+    /// Trọng số của mỗi lượt phê duyệt, mặc định 1
+    pub weight: u8,
+    pub auth: GuardianAuth,
+    pub sign_count: u32,
+    pub nonce: u64,
     pub bump: u8,                    
 }
+
+impl Guardian {
+    pub const SPACE: usize = 8 +
+        32 +
+        8 +
+        4 + 32 +
+        1 +
+        32 +
+        1 +
+        1 +
+        1 +
+        1 + 33 + 32 +
+        4 +
+        8 +
+        1;
+
+    pub const PERMISSION_PROPOSE: u8 = 1 << 0;
+    pub const PERMISSION_APPROVE: u8 = 1 << 1;
+    pub const PERMISSION_EXECUTE: u8 = 1 << 2;
+    pub const PERMISSION_MANAGE_GUARDIANS: u8 = 1 << 3;
+    pub const PERMISSION_INITIATE_RECOVERY: u8 = 1 << 4;
+    pub const ALL_PERMISSIONS: u8 = Self::PERMISSION_PROPOSE |
+        Self::PERMISSION_APPROVE |
+        Self::PERMISSION_EXECUTE |
+        Self::PERMISSION_MANAGE_GUARDIANS |
+        Self::PERMISSION_INITIATE_RECOVERY;
+
+    pub const DEFAULT_WEIGHT: u8 = 1;
+
+    /// Trọng số khi thêm guardian, None = DEFAULT_WEIGHT
+    pub fn resolve_weight(weight: Option<u8>) -> Result<u8> {
+        let weight = weight.unwrap_or(Self::DEFAULT_WEIGHT);
+        require!(weight > 0, WalletError::InvalidWeight);
+        Ok(weight)
+    }
+
+    pub fn has_permission(&self, permission: u8) -> bool {
+        self.permissions & permission == permission
+    }
+
+    /// Owner luôn có đủ mọi quyền; guardian khác có thể có bất kỳ tập con nào
+    pub fn validate_permissions(permissions: u8, is_owner: bool) -> Result<()> {
+        require!(
+            permissions & !Self::ALL_PERMISSIONS == 0 && (!is_owner || permissions == Self::ALL_PERMISSIONS),
+            WalletError::InvalidPermissions
+        );
+        Ok(())
+    }
+}
+
+/// Phiên bản hiện tại của `SigningPayload`, tăng khi đổi cấu trúc payload
+pub const SIGNING_PAYLOAD_VERSION: u8 = 2;
+
+#[cfg(all(feature = "localnet", feature = "mainnet"))]
+compile_error!("Chỉ được bật một trong hai feature `localnet` và `mainnet`");
+
+/// Cluster mà bản build được triển khai: mặc định là devnet, nơi ví đang chạy,
+/// chọn cluster khác bằng feature `mainnet`/`localnet`. Được đưa vào `SigningPayload`
+/// để assertion ký cho một cluster không dùng lại được trên cluster khác dù cùng program ID.
+#[cfg(feature = "localnet")]
+pub const CLUSTER_ID: u8 = 0;
+#[cfg(feature = "mainnet")]
+pub const CLUSTER_ID: u8 = 1;
+#[cfg(not(any(feature = "localnet", feature = "mainnet")))]
+pub const CLUSTER_ID: u8 = 2;
+
+/// Dữ liệu guardian ký bằng WebAuthn, challenge là sha256 của bản Borsh của payload.
+/// `nonce` là nonce hiện tại của guardian và bị tiêu thụ sau mỗi lần xác thực thành công,
+/// nên chữ ký không dùng lại được cho ví, cluster hay đề xuất khác.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SigningPayload {
+    pub version: u8,
+    /// `CLUSTER_ID` của bản build
+    pub cluster: u8,
+    pub program_id: Pubkey,
+    pub multisig: Pubkey,
+    /// Tài khoản mà hành động tác động tới (đề xuất, guardian, yêu cầu khôi phục...)
+    pub target: Pubkey,
+    /// sha256 của mô tả hành động, ví dụ `approve:proposal_1,actions_...`
+    pub action_hash: [u8; 32],
+    pub guardian_id: u64,
+    pub nonce: u64,
+}
+
+/// Cách guardian chứng minh quyền khi hành động.
+/// `Both` yêu cầu cả WebAuthn assertion lẫn chữ ký ed25519 của `signer`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum GuardianAuth {
+    WebAuthn { pubkey: [u8; 33] },
+    Ed25519 { signer: Pubkey },
+    Both { webauthn_pubkey: [u8; 33], signer: Pubkey },
+}
+
+impl GuardianAuth {
+    pub fn webauthn_pubkey(&self) -> Option<[u8; 33]> {
+        match self {
+            GuardianAuth::WebAuthn { pubkey } => Some(*pubkey),
+            GuardianAuth::Both { webauthn_pubkey, .. } => Some(*webauthn_pubkey),
+            GuardianAuth::Ed25519 { .. } => None,
+        }
+    }
+
+    pub fn signer(&self) -> Option<Pubkey> {
+        match self {
+            GuardianAuth::Ed25519 { signer } | GuardianAuth::Both { signer, .. } => Some(*signer),
+            GuardianAuth::WebAuthn { .. } => None,
+        }
+    }
+
+    /// Thay WebAuthn key, giữ nguyên signer ed25519 nếu có
+    pub fn with_webauthn_pubkey(&self, pubkey: [u8; 33]) -> GuardianAuth {
+        match self.signer() {
+            Some(signer) => GuardianAuth::Both { webauthn_pubkey: pubkey, signer },
+            None => GuardianAuth::WebAuthn { pubkey },
+        }
+    }
+
+    /// Bỏ WebAuthn key, trả về None nếu guardian không còn cách xác thực nào
+    pub fn without_webauthn_pubkey(&self) -> Option<GuardianAuth> {
+        self.signer().map(|signer| GuardianAuth::Ed25519 { signer })
+    }
+}
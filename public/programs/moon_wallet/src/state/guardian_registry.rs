@@ -0,0 +1,24 @@
+use anchor_lang::prelude::*;
+
+/// Danh sách guardian_id của ví để client liệt kê guardian mà không cần quét
+/// getProgramAccounts. Seeds: [b"guardian_registry", multisig].
+/// Tài khoản được realloc theo số guardian mỗi khi thêm hoặc xoá guardian.
+#[account]
+pub struct GuardianRegistry {
+    pub multisig: Pubkey,
+    pub guardian_ids: Vec<u64>,
+    pub bump: u8,
+}
+
+impl GuardianRegistry {
+    pub fn space(guardian_count: usize) -> usize {
+        8 +
+        32 +
+        4 + 8 * guardian_count +
+        1
+    }
+
+    pub fn remove(&mut self, guardian_id: u64) {
+        self.guardian_ids.retain(|id| *id != guardian_id);
+    }
+}
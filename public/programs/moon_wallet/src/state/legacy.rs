@@ -0,0 +1,113 @@
+use anchor_lang::prelude::*;
+use crate::state::guardian::Guardian;
+use crate::state::wallet::MultiSigWallet;
+
+// Layout của ví và guardian trước khi đổi seed PDA, chỉ dùng để đọc khi migrate.
+// Dùng chung discriminator với account hiện tại vì đó là cái đã được ghi on-chain.
+// Không dùng #[account] để hai layout này không xuất hiện trong IDL: IDL không cho
+// phép hai account cùng discriminator.
+macro_rules! legacy_account {
+    ($legacy:ident, $current:ident) => {
+        impl Discriminator for $legacy {
+            const DISCRIMINATOR: &'static [u8] = $current::DISCRIMINATOR;
+        }
+
+        impl Owner for $legacy {
+            fn owner() -> Pubkey {
+                crate::ID
+            }
+        }
+
+        impl AccountSerialize for $legacy {
+            fn try_serialize<W: std::io::Write>(&self, writer: &mut W) -> Result<()> {
+                writer
+                    .write_all(Self::DISCRIMINATOR)
+                    .map_err(|_| ErrorCode::AccountDidNotSerialize)?;
+                AnchorSerialize::serialize(self, writer)
+                    .map_err(|_| ErrorCode::AccountDidNotSerialize.into())
+            }
+        }
+
+        impl AccountDeserialize for $legacy {
+            fn try_deserialize(buf: &mut &[u8]) -> Result<Self> {
+                require!(
+                    buf.len() >= Self::DISCRIMINATOR.len(),
+                    ErrorCode::AccountDiscriminatorNotFound
+                );
+                require!(
+                    &buf[..Self::DISCRIMINATOR.len()] == Self::DISCRIMINATOR,
+                    ErrorCode::AccountDiscriminatorMismatch
+                );
+                Self::try_deserialize_unchecked(buf)
+            }
+
+            fn try_deserialize_unchecked(buf: &mut &[u8]) -> Result<Self> {
+                let mut data: &[u8] = &buf[Self::DISCRIMINATOR.len()..];
+                AnchorDeserialize::deserialize(&mut data)
+                    .map_err(|_| ErrorCode::AccountDidNotDeserialize.into())
+            }
+        }
+
+        #[cfg(feature = "idl-build")]
+        impl IdlBuild for $legacy {}
+    };
+}
+
+#[derive(borsh::BorshSerialize, borsh::BorshDeserialize, Clone)]
+pub struct LegacyMultiSigWallet {
+    pub threshold: u8,
+    pub guardian_count: u8,
+    pub recovery_nonce: u64,
+    pub bump: u8,
+    pub transaction_nonce: u64,
+    pub last_transaction_timestamp: i64,
+    pub owner: Pubkey,
+    pub credential_id: String,
+}
+
+#[derive(borsh::BorshSerialize, borsh::BorshDeserialize, Clone)]
+pub struct LegacyGuardian {
+    pub wallet: Pubkey,
+    pub guardian_id: u64,
+    pub name: String,
+    pub is_active: bool,
+    pub recovery_hash: [u8; 32],
+    pub is_owner: bool,
+    pub webauthn_pubkey: Option<[u8; 33]>,
+    pub bump: u8,
+}
+
+legacy_account!(LegacyMultiSigWallet, MultiSigWallet);
+legacy_account!(LegacyGuardian, Guardian);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_accounts_keep_current_discriminators() {
+        assert_eq!(LegacyMultiSigWallet::DISCRIMINATOR, MultiSigWallet::DISCRIMINATOR);
+        assert_eq!(LegacyGuardian::DISCRIMINATOR, Guardian::DISCRIMINATOR);
+    }
+
+    #[test]
+    fn legacy_guardian_reads_baseline_layout() {
+        let legacy = LegacyGuardian {
+            wallet: Pubkey::new_unique(),
+            guardian_id: 7,
+            name: "phone".to_string(),
+            is_active: true,
+            recovery_hash: [1; 32],
+            is_owner: true,
+            webauthn_pubkey: Some([2; 33]),
+            bump: 254,
+        };
+        let mut data = Vec::new();
+        legacy.try_serialize(&mut data).unwrap();
+
+        let decoded = LegacyGuardian::try_deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(decoded.guardian_id, 7);
+        assert_eq!(decoded.webauthn_pubkey, Some([2; 33]));
+        assert_eq!(decoded.bump, 254);
+    }
+}
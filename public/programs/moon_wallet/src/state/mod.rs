@@ -1,7 +1,17 @@
 pub mod wallet;
 pub mod guardian;
 pub mod proposal;
+pub mod recovery;
+pub mod spending_limit;
+pub mod address_book;
+pub mod guardian_registry;
+pub mod legacy;
 
 pub use guardian::*;
 pub use wallet::*;
-pub use proposal::*; 
\ No newline at end of file
+pub use proposal::*;
+pub use recovery::*;
+pub use spending_limit::*;
+pub use address_book::*;
+pub use guardian_registry::*;
+pub use legacy::*; 
\ No newline at end of file
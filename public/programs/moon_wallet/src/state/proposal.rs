@@ -1,22 +1,87 @@
 use anchor_lang::prelude::*;
-use crate::state::wallet::ActionParams;
+use anchor_lang::solana_program::hash::hash;
+use crate::errors::*;
+use crate::state::guardian::GuardianAuth;
+use crate::state::wallet::DestinationPolicy;
 
 #[account]
 pub struct TransactionProposal {
     pub multisig: Pubkey,             
     pub proposal_id: u64,             
     pub proposer: Pubkey,             
+    pub payer: Pubkey,
     pub description: String,          
-    pub action: String,               
-    pub params: ActionParams,         
+    pub actions: Vec<ProposalAction>,
+    pub contents_hash: [u8; 32],
+    pub executed_actions: u8,
     pub status: ProposalStatus,       
     pub created_at: i64,              
     pub executed_at: Option<i64>,     
+    pub expires_at: Option<i64>,
     pub signatures_count: u8,         
     pub required_signatures: u8,      
+    pub approval_weight: u16,
+    pub required_weight: u16,
+    pub rejections_count: u8,
+    pub required_rejections: u8,
+    /// Số ProposalSignature/ProposalRejection đã đóng, close_proposal có thể chia nhiều giao dịch
+    pub closed_signatures: u8,
+    pub closed_rejections: u8,
     pub bump: u8,                     
 }
 
+/// Số hành động tối đa trong một đề xuất
+pub const MAX_PROPOSAL_ACTIONS: usize = 16;
+
+impl TransactionProposal {
+    /// Đề xuất có ngưỡng trọng số thì xét tổng trọng số, nếu không thì xét số chữ ký
+    pub fn has_enough_approvals(&self) -> bool {
+        if self.required_weight > 0 {
+            self.approval_weight >= self.required_weight
+        } else {
+            self.signatures_count >= self.required_signatures
+        }
+    }
+
+    /// Số phê duyệt dùng cho chính sách địa chỉ đích: tổng trọng số khi ví bỏ phiếu theo trọng số
+    pub fn approvals(&self) -> u16 {
+        if self.required_weight > 0 {
+            self.approval_weight
+        } else {
+            self.signatures_count as u16
+        }
+    }
+
+    /// Ghi nhận số PDA chữ ký/từ chối vừa được đóng trong một lần gọi close_proposal.
+    /// Trả về true khi mọi PDA đã được đóng và có thể đóng đề xuất
+    pub fn record_closed(&mut self, signatures: u8, rejections: u8) -> Result<bool> {
+        self.closed_signatures = self.closed_signatures
+            .checked_add(signatures)
+            .ok_or(WalletError::InvalidSignatureCount)?;
+        self.closed_rejections = self.closed_rejections
+            .checked_add(rejections)
+            .ok_or(WalletError::InvalidSignatureCount)?;
+        require!(
+            self.closed_signatures <= self.signatures_count
+                && self.closed_rejections <= self.rejections_count,
+            WalletError::InvalidSignatureCount
+        );
+
+        Ok(self.closed_signatures == self.signatures_count
+            && self.closed_rejections == self.rejections_count)
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        matches!(self.expires_at, Some(expires_at) if now >= expires_at)
+    }
+
+    /// sha256 của nội dung sẽ được thực thi, được đưa vào message guardian ký khi tạo,
+    /// phê duyệt và từ chối đề xuất
+    pub fn compute_contents_hash(multisig: &Pubkey, actions: &[ProposalAction]) -> Result<[u8; 32]> {
+        Ok(hash(&(multisig, actions).try_to_vec()?).to_bytes())
+    }
+}
+
 #[account]
 pub struct ProposalSignature {
     pub proposal: Pubkey,             
@@ -25,10 +90,296 @@ pub struct ProposalSignature {
     pub bump: u8,                     
 }
 
+#[account]
+pub struct ProposalRejection {
+    pub proposal: Pubkey,
+    pub guardian_id: u64,
+    pub rejection_time: i64,
+    pub bump: u8,
+}
+
+/// Hành động mà đề xuất sẽ thực thi khi đủ chữ ký
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum ProposalAction {
+    /// Chuyển `amount` lamports từ ví multisig đến `destination`
+    TransferSol { amount: u64, destination: Pubkey },
+    /// Chuyển `amount` token của `mint` từ token account của ví multisig đến `destination`.
+    /// Token được gửi vào `recipient_token_account` nếu có, nếu không thì vào ATA của `destination`.
+    TransferToken {
+        mint: Pubkey,
+        amount: u64,
+        destination: Pubkey,
+        recipient_token_account: Option<Pubkey>,
+    },
+    /// Gọi lần lượt các instruction bất kỳ với ví multisig và vault của ví làm signer.
+    /// Ví multisig thuộc chương trình và có dữ liệu nên System Program không thể trừ lamports
+    /// của nó; các lệnh chuyển SOL qua System Program phải dùng vault (PDA ["vault", multisig],
+    /// thuộc System Program, không có dữ liệu) làm tài khoản nguồn.
+    Invoke { instructions: Vec<ProposalInstruction> },
+    /// Đổi ngưỡng phê duyệt và ngưỡng từ chối (0 = mặc định theo số guardian)
+    ChangeThreshold { threshold: u8, rejection_threshold: u8 },
+    /// Đổi tổng trọng số phê duyệt cần để thực thi (0 = dùng ngưỡng số chữ ký)
+    ChangeWeightThreshold { weight_threshold: u16 },
+    /// Thêm guardian mới; tài khoản guardian được truyền qua remaining_accounts
+    AddGuardian {
+        guardian_id: u64,
+        name: String,
+        recovery_hash_intermediate: [u8; 32],
+        is_owner: bool,
+        permissions: u8,
+        weight: Option<u8>,
+        auth: GuardianAuth,
+    },
+    /// Xoá guardian; tài khoản guardian được truyền qua remaining_accounts
+    RemoveGuardian { guardian_id: u64 },
+    /// Đổi thời gian chờ (giây) trước khi yêu cầu khôi phục được hoàn tất
+    ChangeRecoveryDelay { recovery_delay: i64 },
+    /// Đổi số guardian cần phê duyệt một yêu cầu khôi phục
+    ChangeRecoveryThreshold { recovery_threshold: u8 },
+    /// Đổi số guardian tối đa của ví
+    ChangeMaxGuardians { max_guardians: u8 },
+    /// Tạo hoặc cập nhật hạn mức chi tiêu của `mint` (Pubkey::default() cho SOL);
+    /// tài khoản SpendingLimit được truyền qua remaining_accounts
+    SetSpendingLimit {
+        mint: Pubkey,
+        amount: u64,
+        period: i64,
+        destinations: Vec<Pubkey>,
+    },
+    /// Xoá hạn mức chi tiêu của `mint`
+    RemoveSpendingLimit { mint: Pubkey },
+    /// Thêm địa chỉ vào sổ địa chỉ; tài khoản AddressBook được truyền qua remaining_accounts
+    AddAddressBookEntry { address: Pubkey, label: String },
+    /// Xoá địa chỉ khỏi sổ địa chỉ
+    RemoveAddressBookEntry { address: Pubkey },
+    /// Đổi chính sách với địa chỉ đích ngoài sổ địa chỉ
+    SetDestinationPolicy { policy: DestinationPolicy },
+    /// Đổi trọng số phê duyệt của guardian; tài khoản guardian được truyền qua remaining_accounts
+    ChangeGuardianWeight { guardian_id: u64, weight: u8 },
+}
+
+/// Instruction được lưu trong đề xuất, tương đương solana_program::instruction::Instruction
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub struct ProposalInstruction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<ProposalAccountMeta>,
+    pub data: Vec<u8>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub struct ProposalAccountMeta {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+impl ProposalInstruction {
+    pub fn space(&self) -> usize {
+        32 + 4 + self.accounts.len() * (32 + 1 + 1) + 4 + self.data.len()
+    }
+}
+
+impl ProposalAction {
+    /// Số bytes khi serialize, dùng để cấp phát TransactionProposal
+    pub fn space(&self) -> usize {
+        1 + match self {
+            ProposalAction::TransferSol { .. } => 8 + 32,
+            ProposalAction::TransferToken { .. } => 32 + 8 + 32 + 1 + 32,
+            ProposalAction::Invoke { instructions } => {
+                4 + instructions.iter().map(|ix| ix.space()).sum::<usize>()
+            },
+            ProposalAction::ChangeThreshold { .. } => 1 + 1,
+            ProposalAction::ChangeWeightThreshold { .. } => 2,
+            ProposalAction::AddGuardian { name, .. } => {
+                8 + 4 + name.len() + 32 + 1 + 1 + 1 + 1 + 1 + 33 + 32
+            },
+            ProposalAction::RemoveGuardian { .. } => 8,
+            ProposalAction::ChangeRecoveryDelay { .. } => 8,
+            ProposalAction::ChangeRecoveryThreshold { .. } => 1,
+            ProposalAction::ChangeMaxGuardians { .. } => 1,
+            ProposalAction::SetSpendingLimit { destinations, .. } => {
+                32 + 8 + 8 + 4 + 32 * destinations.len()
+            },
+            ProposalAction::RemoveSpendingLimit { .. } => 32,
+            ProposalAction::AddAddressBookEntry { label, .. } => 32 + 4 + label.len(),
+            ProposalAction::RemoveAddressBookEntry { .. } => 32,
+            ProposalAction::SetDestinationPolicy { .. } => 1 + 1,
+            ProposalAction::ChangeGuardianWeight { .. } => 8 + 1,
+        }
+    }
+
+    /// Địa chỉ nhận tiền nếu hành động là chuyển SOL/token
+    pub fn transfer_destination(&self) -> Option<Pubkey> {
+        match self {
+            ProposalAction::TransferSol { destination, .. } |
+            ProposalAction::TransferToken { destination, .. } => Some(*destination),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProposalAction::TransferSol { .. } => "transfer_sol",
+            ProposalAction::TransferToken { .. } => "transfer_token",
+            ProposalAction::Invoke { .. } => "invoke",
+            ProposalAction::ChangeThreshold { .. } => "change_threshold",
+            ProposalAction::ChangeWeightThreshold { .. } => "change_weight_threshold",
+            ProposalAction::AddGuardian { .. } => "add_guardian",
+            ProposalAction::RemoveGuardian { .. } => "remove_guardian",
+            ProposalAction::ChangeRecoveryDelay { .. } => "change_recovery_delay",
+            ProposalAction::ChangeRecoveryThreshold { .. } => "change_recovery_threshold",
+            ProposalAction::ChangeMaxGuardians { .. } => "change_max_guardians",
+            ProposalAction::SetSpendingLimit { .. } => "set_spending_limit",
+            ProposalAction::RemoveSpendingLimit { .. } => "remove_spending_limit",
+            ProposalAction::AddAddressBookEntry { .. } => "add_address_book_entry",
+            ProposalAction::RemoveAddressBookEntry { .. } => "remove_address_book_entry",
+            ProposalAction::SetDestinationPolicy { .. } => "set_destination_policy",
+            ProposalAction::ChangeGuardianWeight { .. } => "change_guardian_weight",
+        }
+    }
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
 pub enum ProposalStatus {
     Pending,        
+    /// Đã thực thi một phần, tiếp tục từ `executed_actions`
+    Executing,
     Executed,       
     Rejected,       
     Expired,        
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proposal(signatures_count: u8, rejections_count: u8) -> TransactionProposal {
+        TransactionProposal {
+            multisig: Pubkey::new_unique(),
+            proposal_id: 0,
+            proposer: Pubkey::new_unique(),
+            payer: Pubkey::new_unique(),
+            description: String::new(),
+            actions: Vec::new(),
+            contents_hash: [0; 32],
+            executed_actions: 0,
+            status: ProposalStatus::Rejected,
+            created_at: 0,
+            executed_at: None,
+            expires_at: None,
+            signatures_count,
+            required_signatures: 2,
+            approval_weight: 0,
+            required_weight: 0,
+            rejections_count,
+            required_rejections: 2,
+            closed_signatures: 0,
+            closed_rejections: 0,
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn close_is_complete_only_after_every_batch() {
+        let mut proposal = proposal(3, 2);
+        assert!(!proposal.record_closed(2, 0).unwrap());
+        assert!(!proposal.record_closed(1, 1).unwrap());
+        assert!(proposal.record_closed(0, 1).unwrap());
+    }
+
+    #[test]
+    fn proposal_without_signatures_closes_in_one_call() {
+        assert!(proposal(0, 0).record_closed(0, 0).unwrap());
+    }
+
+    #[test]
+    fn closing_more_accounts_than_recorded_fails() {
+        let mut proposal = proposal(1, 1);
+        assert!(!proposal.record_closed(1, 0).unwrap());
+        assert_eq!(
+            proposal.record_closed(1, 0).err(),
+            Some(WalletError::InvalidSignatureCount.into())
+        );
+        assert_eq!(
+            proposal.record_closed(0, 2).err(),
+            Some(WalletError::InvalidSignatureCount.into())
+        );
+    }
+
+    /// Mỗi loại hành động ở dạng lớn nhất khi serialize
+    fn largest_actions() -> Vec<ProposalAction> {
+        let key = Pubkey::new_unique();
+        vec![
+            ProposalAction::TransferSol { amount: 1, destination: key },
+            ProposalAction::TransferToken {
+                mint: key,
+                amount: 1,
+                destination: key,
+                recipient_token_account: Some(key),
+            },
+            ProposalAction::Invoke {
+                instructions: vec![
+                    ProposalInstruction {
+                        program_id: key,
+                        accounts: vec![ProposalAccountMeta { pubkey: key, is_signer: true, is_writable: true }; 3],
+                        data: vec![1; 10],
+                    },
+                    ProposalInstruction { program_id: key, accounts: Vec::new(), data: Vec::new() },
+                ],
+            },
+            ProposalAction::ChangeThreshold { threshold: 2, rejection_threshold: 1 },
+            ProposalAction::ChangeWeightThreshold { weight_threshold: 300 },
+            ProposalAction::AddGuardian {
+                guardian_id: 1,
+                name: "guardian".to_string(),
+                recovery_hash_intermediate: [1; 32],
+                is_owner: false,
+                permissions: 2,
+                weight: Some(3),
+                auth: GuardianAuth::Both { webauthn_pubkey: [2; 33], signer: key },
+            },
+            ProposalAction::RemoveGuardian { guardian_id: 1 },
+            ProposalAction::ChangeRecoveryDelay { recovery_delay: 60 },
+            ProposalAction::ChangeRecoveryThreshold { recovery_threshold: 1 },
+            ProposalAction::ChangeMaxGuardians { max_guardians: 8 },
+            ProposalAction::SetSpendingLimit { mint: key, amount: 1, period: 60, destinations: vec![key; 2] },
+            ProposalAction::RemoveSpendingLimit { mint: key },
+            ProposalAction::AddAddressBookEntry { address: key, label: "label".to_string() },
+            ProposalAction::RemoveAddressBookEntry { address: key },
+            ProposalAction::SetDestinationPolicy { policy: DestinationPolicy::ElevatedThreshold { threshold: 2 } },
+            ProposalAction::ChangeGuardianWeight { guardian_id: 1, weight: 2 },
+        ]
+    }
+
+    #[test]
+    fn action_space_matches_serialized_length() {
+        for action in largest_actions() {
+            assert_eq!(action.space(), action.try_to_vec().unwrap().len(), "{}", action.name());
+        }
+    }
+
+    #[test]
+    fn action_space_covers_smaller_variants() {
+        let key = Pubkey::new_unique();
+        let actions = [
+            ProposalAction::TransferToken {
+                mint: key,
+                amount: 1,
+                destination: key,
+                recipient_token_account: None,
+            },
+            ProposalAction::AddGuardian {
+                guardian_id: 1,
+                name: String::new(),
+                recovery_hash_intermediate: [1; 32],
+                is_owner: false,
+                permissions: 2,
+                weight: None,
+                auth: GuardianAuth::WebAuthn { pubkey: [2; 33] },
+            },
+            ProposalAction::SetDestinationPolicy { policy: DestinationPolicy::AllowlistOnly },
+        ];
+        for action in actions {
+            assert!(action.space() >= action.try_to_vec().unwrap().len(), "{}", action.name());
+        }
+    }
+}
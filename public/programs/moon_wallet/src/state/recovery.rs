@@ -0,0 +1,45 @@
+use anchor_lang::prelude::*;
+use crate::state::wallet::MAX_GUARDIANS;
+
+/// Yêu cầu khôi phục đang chờ, chỉ hoàn tất được sau `executable_at` và khi
+/// đủ `required_approvals` guardian phê duyệt.
+/// Seeds: [b"recovery", multisig, recovery_nonce] nên mỗi nonce chỉ có một yêu cầu;
+/// sau `expires_at` bất kỳ ai cũng có thể dọn yêu cầu để mở nonce mới.
+#[account]
+pub struct RecoveryRequest {
+    pub multisig: Pubkey,
+    pub nonce: u64,
+    pub old_guardian_id: u64,
+    pub new_guardian_id: u64,
+    pub new_webauthn_pubkey: [u8; 33],
+    pub initiator: Pubkey,
+    pub created_at: i64,
+    pub executable_at: i64,
+    pub expires_at: i64,
+    pub required_approvals: u8,
+    pub approvers: Vec<u64>,
+    pub bump: u8,
+}
+
+impl RecoveryRequest {
+    /// Thời gian yêu cầu còn hiệu lực sau `executable_at`
+    pub const EXPIRY_WINDOW: i64 = 7 * 24 * 60 * 60;
+
+    pub const SPACE: usize = 8 +
+        32 +
+        8 +
+        8 +
+        8 +
+        33 +
+        32 +
+        8 +
+        8 +
+        8 +
+        1 +
+        4 + 8 * MAX_GUARDIANS +
+        1;
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        now >= self.expires_at
+    }
+}
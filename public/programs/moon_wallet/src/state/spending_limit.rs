@@ -0,0 +1,151 @@
+use anchor_lang::prelude::*;
+use crate::errors::*;
+
+/// Số địa chỉ đích tối đa trong allowlist của một hạn mức
+pub const MAX_SPENDING_LIMIT_DESTINATIONS: usize = 10;
+
+/// Số khoản chi tối đa được ghi lại trong một cửa sổ `period`
+pub const MAX_SPENDING_LIMIT_RECORDS: usize = 32;
+
+/// Một khoản chi đã thực hiện trong hạn mức
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct SpendRecord {
+    pub timestamp: i64,
+    pub amount: u64,
+}
+
+/// Hạn mức chi tiêu cho phép owner chuyển tiền mà không cần đề xuất.
+/// Tổng các khoản chi trong `period` giây gần nhất (cửa sổ trượt) không vượt quá `amount`.
+/// Seeds: [b"spending_limit", multisig, mint], `mint` = Pubkey::default() cho SOL.
+#[account]
+pub struct SpendingLimit {
+    pub multisig: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub period: i64,
+    /// Các khoản chi còn nằm trong cửa sổ, cũ nhất trước
+    pub spends: Vec<SpendRecord>,
+    pub destinations: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl SpendingLimit {
+    pub const SPACE: usize = 8 +
+        32 +
+        32 +
+        8 +
+        8 +
+        4 + (8 + 8) * MAX_SPENDING_LIMIT_RECORDS +
+        4 + 32 * MAX_SPENDING_LIMIT_DESTINATIONS +
+        1;
+
+    /// Tổng đã chi trong cửa sổ hiện tại
+    pub fn spent(&self) -> Result<u64> {
+        self.spends.iter().try_fold(0u64, |total, spend| {
+            total.checked_add(spend.amount).ok_or_else(|| WalletError::ArithmeticOverflow.into())
+        })
+    }
+
+    /// Ghi nhận một khoản chi sau khi bỏ các khoản đã ra khỏi cửa sổ `period` giây
+    pub fn record_spend(&mut self, amount: u64, now: i64) -> Result<()> {
+        let period = self.period;
+        self.spends.retain(|spend| now < spend.timestamp.saturating_add(period));
+
+        let spent = self.spent()?
+            .checked_add(amount)
+            .ok_or(WalletError::ArithmeticOverflow)?;
+        require!(spent <= self.amount, WalletError::SpendingLimitExceeded);
+        require!(
+            self.spends.len() < MAX_SPENDING_LIMIT_RECORDS,
+            WalletError::LimitExceeded
+        );
+
+        self.spends.push(SpendRecord { timestamp: now, amount });
+        Ok(())
+    }
+
+    pub fn allows_destination(&self, destination: &Pubkey) -> bool {
+        self.destinations.is_empty() || self.destinations.contains(destination)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(amount: u64, period: i64) -> SpendingLimit {
+        SpendingLimit {
+            multisig: Pubkey::new_unique(),
+            mint: Pubkey::default(),
+            amount,
+            period,
+            spends: Vec::new(),
+            destinations: Vec::new(),
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn record_spend_accumulates_within_period() {
+        let mut limit = limit(100, 60);
+        limit.record_spend(40, 110).unwrap();
+        limit.record_spend(60, 159).unwrap();
+        assert_eq!(limit.spent().unwrap(), 100);
+        assert_eq!(
+            limit.record_spend(1, 159).err(),
+            Some(WalletError::SpendingLimitExceeded.into())
+        );
+        assert_eq!(limit.spent().unwrap(), 100);
+    }
+
+    #[test]
+    fn record_spend_uses_a_rolling_window() {
+        let mut limit = limit(100, 60);
+        limit.record_spend(60, 110).unwrap();
+        limit.record_spend(40, 150).unwrap();
+
+        // Khoản 60 lúc 110 hết hạn lúc 170, khoản 40 lúc 150 vẫn còn tính
+        assert_eq!(
+            limit.record_spend(61, 170).err(),
+            Some(WalletError::SpendingLimitExceeded.into())
+        );
+        limit.record_spend(60, 170).unwrap();
+        assert_eq!(limit.spent().unwrap(), 100);
+
+        limit.record_spend(40, 210).unwrap();
+        assert_eq!(limit.spent().unwrap(), 100);
+    }
+
+    #[test]
+    fn record_spend_caps_the_number_of_records() {
+        let mut limit = limit(u64::MAX, 60);
+        for _ in 0..MAX_SPENDING_LIMIT_RECORDS {
+            limit.record_spend(1, 110).unwrap();
+        }
+        assert_eq!(
+            limit.record_spend(1, 110).err(),
+            Some(WalletError::LimitExceeded.into())
+        );
+        limit.record_spend(1, 170).unwrap();
+    }
+
+    #[test]
+    fn record_spend_rejects_overflow() {
+        let mut limit = limit(u64::MAX, 60);
+        limit.record_spend(u64::MAX, 110).unwrap();
+        assert_eq!(
+            limit.record_spend(1, 110).err(),
+            Some(WalletError::ArithmeticOverflow.into())
+        );
+    }
+
+    #[test]
+    fn empty_destination_list_allows_any_destination() {
+        let mut limit = limit(100, 60);
+        let destination = Pubkey::new_unique();
+        assert!(limit.allows_destination(&destination));
+
+        limit.destinations.push(Pubkey::new_unique());
+        assert!(!limit.allows_destination(&destination));
+    }
+}
@@ -1,5 +1,12 @@
 use anchor_lang::prelude::*;
+use crate::errors::*;
+use crate::state::guardian::Guardian;
 
+/// Giới hạn trên của `max_guardians` mà một ví có thể cấu hình
+pub const MAX_GUARDIANS: usize = 64;
+
+/// `max_guardians` của ví mới tạo
+pub const DEFAULT_MAX_GUARDIANS: u8 = 8;
 
 #[account]
 pub struct MultiSigWallet {
@@ -11,12 +18,337 @@ pub struct MultiSigWallet {
     pub last_transaction_timestamp: i64, 
     pub owner: Pubkey,               
     pub credential_id: String,       
+    pub rp_id_hash: [u8; 32],
+    pub rejection_threshold: u8,
+    pub proposal_ttl: i64,
+    pub active_guardian_count: u8,
+    pub owner_count: u8,
+    pub recovery_delay: i64,
+    pub recovery_threshold: u8,
+    pub destination_policy: DestinationPolicy,
+    /// ID của đề xuất tiếp theo, do chương trình cấp khi tạo đề xuất
+    pub next_proposal_id: u64,
+    /// Tổng trọng số phê duyệt cần để thực thi đề xuất (0 = tắt, chỉ xét `threshold` chữ ký)
+    pub weight_threshold: u16,
+    /// Tổng trọng số của các guardian đang hoạt động
+    pub active_weight: u16,
+    /// Số guardian tối đa của ví, không vượt quá MAX_GUARDIANS
+    pub max_guardians: u8,
+}
+
+/// Chính sách với các địa chỉ đích không có trong sổ địa chỉ
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum DestinationPolicy {
+    Unrestricted,
+    AllowlistOnly,
+    /// Chuyển tới địa chỉ ngoài sổ cần ít nhất `threshold` chữ ký,
+    /// hoặc `threshold` trọng số phê duyệt khi ví bỏ phiếu theo trọng số
+    ElevatedThreshold { threshold: u8 },
+}
+
+impl DestinationPolicy {
+    /// Kiểm tra một lần chuyển tới địa chỉ có (hoặc không) trong sổ địa chỉ với số phê duyệt đã có
+    /// (số chữ ký, hoặc tổng trọng số khi bỏ phiếu theo trọng số)
+    pub fn check(&self, listed: bool, approvals: u16) -> Result<()> {
+        match self {
+            DestinationPolicy::Unrestricted => Ok(()),
+            _ if listed => Ok(()),
+            DestinationPolicy::AllowlistOnly => err!(WalletError::DestinationNotAllowed),
+            DestinationPolicy::ElevatedThreshold { threshold } => {
+                require!(approvals >= *threshold as u16, WalletError::ElevatedThresholdNotMet);
+                Ok(())
+            },
+        }
+    }
+}
+
+impl MultiSigWallet {
+    pub fn space(credential_id_len: usize) -> usize {
+        8 +
+        1 +
+        1 +
+        8 +
+        1 +
+        8 +
+        8 +
+        32 +
+        4 + credential_id_len +
+        32 +
+        1 +
+        8 +
+        1 +
+        1 +
+        8 +
+        1 +
+        1 + 1 +
+        8 +
+        2 +
+        2 +
+        1
+    }
+
+    /// Số lượt từ chối cần để bác bỏ đề xuất. Mặc định là khi không còn
+    /// đủ guardian hoạt động để đạt ngưỡng phê duyệt: active_guardian_count - threshold + 1.
+    pub fn rejection_quorum(&self) -> u8 {
+        if self.rejection_threshold > 0 {
+            return self.rejection_threshold;
+        }
+        self.active_guardian_count
+            .saturating_sub(self.threshold)
+            .saturating_add(1)
+    }
+
+    /// Ngưỡng phải nằm trong khoảng 1..=số guardian đang hoạt động
+    pub fn validate_threshold(&self, threshold: u8) -> Result<()> {
+        require!(
+            threshold > 0 && threshold <= self.active_guardian_count,
+            WalletError::InvalidThreshold
+        );
+        Ok(())
+    }
+
+    /// Trọng số phê duyệt mà đề xuất mới cần đạt, 0 khi ví chỉ đếm số chữ ký
+    pub fn required_weight(&self) -> u16 {
+        self.weight_threshold
+    }
+
+    /// Ngưỡng trọng số phải đạt được bởi các guardian đang hoạt động (0 = tắt)
+    pub fn validate_weight_threshold(&self, weight_threshold: u16) -> Result<()> {
+        require!(
+            weight_threshold <= self.active_weight,
+            WalletError::InvalidThreshold
+        );
+        Ok(())
+    }
+
+    /// Giới hạn mới phải nằm trong khoảng số guardian hiện có..=MAX_GUARDIANS
+    pub fn validate_max_guardians(&self, max_guardians: u8) -> Result<()> {
+        require!(
+            max_guardians > 0 &&
+                max_guardians >= self.guardian_count &&
+                max_guardians as usize <= MAX_GUARDIANS,
+            WalletError::InvalidConfig
+        );
+        Ok(())
+    }
+
+    pub fn on_guardian_added(&mut self, is_owner: bool, weight: u8) -> Result<()> {
+        require!(self.guardian_count < self.max_guardians, WalletError::LimitExceeded);
+
+        self.active_weight = self.active_weight
+            .checked_add(weight as u16)
+            .ok_or(WalletError::ArithmeticOverflow)?;
+        self.guardian_count += 1;
+        self.active_guardian_count += 1;
+        if is_owner {
+            self.owner_count += 1;
+        }
+        Ok(())
+    }
+
+    /// Không cho xoá owner cuối cùng hoặc làm số guardian (hay trọng số) hoạt động thấp hơn ngưỡng
+    pub fn on_guardian_removed(&mut self, guardian: &Guardian) -> Result<()> {
+        require!(self.guardian_count > 0, WalletError::GuardianError);
+        if guardian.is_owner {
+            require!(self.owner_count > 1, WalletError::LastOwner);
+            self.owner_count -= 1;
+        }
+        if guardian.is_active {
+            self.on_guardian_deactivated(guardian)?;
+        }
+        self.guardian_count -= 1;
+        Ok(())
+    }
+
+    pub fn on_guardian_status_changed(&mut self, guardian: &Guardian, is_active: bool) -> Result<()> {
+        if guardian.is_active == is_active {
+            return Ok(());
+        }
+        if is_active {
+            self.active_weight = self.active_weight
+                .checked_add(guardian.weight as u16)
+                .ok_or(WalletError::ArithmeticOverflow)?;
+            self.active_guardian_count += 1;
+        } else {
+            self.on_guardian_deactivated(guardian)?;
+        }
+        Ok(())
+    }
+
+    /// Guardian đang hoạt động đổi trọng số thì tổng trọng số còn lại vẫn phải đạt ngưỡng
+    pub fn on_guardian_weight_changed(&mut self, guardian: &Guardian, weight: u8) -> Result<()> {
+        if !guardian.is_active {
+            return Ok(());
+        }
+        let active_weight = self.active_weight
+            .checked_add(weight as u16)
+            .and_then(|w| w.checked_sub(guardian.weight as u16))
+            .ok_or(WalletError::ArithmeticOverflow)?;
+        require!(active_weight >= self.required_weight(), WalletError::InvalidThreshold);
+        self.active_weight = active_weight;
+        Ok(())
+    }
+
+    /// Recovery vô hiệu hoá owner cũ không còn cách xác thực nào. Khác với xoá/tắt guardian
+    /// thông thường, recovery không bị chặn bởi ngưỡng: các ngưỡng được hạ xuống mức mà
+    /// guardian còn lại đạt được để ví không bị khoá vĩnh viễn.
+    pub fn on_guardian_deactivated_by_recovery(&mut self, guardian: &Guardian) {
+        self.active_guardian_count = self.active_guardian_count.saturating_sub(1);
+        self.active_weight = self.active_weight.saturating_sub(guardian.weight as u16);
+
+        self.threshold = self.threshold.min(self.active_guardian_count);
+        self.rejection_threshold = self.rejection_threshold.min(self.active_guardian_count);
+        self.recovery_threshold = self.recovery_threshold.min(self.active_guardian_count);
+        self.weight_threshold = self.weight_threshold.min(self.active_weight);
+    }
+
+    fn on_guardian_deactivated(&mut self, guardian: &Guardian) -> Result<()> {
+        let active_weight = self.active_weight.saturating_sub(guardian.weight as u16);
+        require!(
+            self.active_guardian_count > self.threshold && active_weight >= self.required_weight(),
+            WalletError::InvalidThreshold
+        );
+        self.active_guardian_count -= 1;
+        self.active_weight = active_weight;
+        Ok(())
+    }
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct ActionParams {
-    pub amount: Option<u64>,        
-    pub destination: Option<Pubkey>, 
-    pub token_mint: Option<Pubkey>,
-    pub token_amount: Option<u64>,   
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::guardian::GuardianAuth;
+
+    fn wallet(guardian_count: u8, threshold: u8, rejection_threshold: u8) -> MultiSigWallet {
+        MultiSigWallet {
+            threshold,
+            guardian_count,
+            recovery_nonce: 0,
+            bump: 255,
+            transaction_nonce: 0,
+            last_transaction_timestamp: 0,
+            owner: Pubkey::new_unique(),
+            credential_id: String::new(),
+            rp_id_hash: [0; 32],
+            rejection_threshold,
+            proposal_ttl: 0,
+            active_guardian_count: guardian_count,
+            owner_count: 1,
+            recovery_delay: 0,
+            recovery_threshold: 1,
+            destination_policy: DestinationPolicy::Unrestricted,
+            next_proposal_id: 0,
+            weight_threshold: 0,
+            active_weight: guardian_count as u16,
+            max_guardians: DEFAULT_MAX_GUARDIANS,
+        }
+    }
+
+    fn guardian(weight: u8, is_active: bool) -> Guardian {
+        Guardian {
+            wallet: Pubkey::new_unique(),
+            guardian_id: 1,
+            name: String::new(),
+            is_active,
+            recovery_hash: [0; 32],
+            is_owner: false,
+            permissions: 0,
+            weight,
+            auth: GuardianAuth::Ed25519 { signer: Pubkey::new_unique() },
+            sign_count: 0,
+            nonce: 0,
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn rejection_quorum_defaults_to_blocking_threshold() {
+        assert_eq!(wallet(5, 3, 0).rejection_quorum(), 3);
+        assert_eq!(wallet(3, 3, 0).rejection_quorum(), 1);
+        assert_eq!(wallet(1, 1, 0).rejection_quorum(), 1);
+    }
+
+    #[test]
+    fn rejection_quorum_ignores_inactive_guardians() {
+        let mut wallet = wallet(5, 3, 0);
+        wallet.active_guardian_count = 4;
+        assert_eq!(wallet.rejection_quorum(), 2);
+    }
+
+    #[test]
+    fn rejection_quorum_uses_configured_threshold() {
+        assert_eq!(wallet(5, 3, 2).rejection_quorum(), 2);
+    }
+
+    #[test]
+    fn unrestricted_policy_allows_any_destination() {
+        assert!(DestinationPolicy::Unrestricted.check(false, 0).is_ok());
+    }
+
+    #[test]
+    fn allowlist_only_policy_rejects_unlisted_destination() {
+        let policy = DestinationPolicy::AllowlistOnly;
+        assert!(policy.check(true, 1).is_ok());
+        assert_eq!(policy.check(false, 10).err(), Some(WalletError::DestinationNotAllowed.into()));
+    }
+
+    #[test]
+    fn elevated_threshold_policy_requires_more_signatures_for_unlisted_destination() {
+        let policy = DestinationPolicy::ElevatedThreshold { threshold: 3 };
+        assert!(policy.check(true, 1).is_ok());
+        assert!(policy.check(false, 3).is_ok());
+        assert_eq!(policy.check(false, 2).err(), Some(WalletError::ElevatedThresholdNotMet.into()));
+    }
+
+    #[test]
+    fn guardian_weight_change_updates_active_weight() {
+        let mut wallet = wallet(3, 2, 0);
+        wallet.weight_threshold = 3;
+        wallet.on_guardian_weight_changed(&guardian(1, true), 4).unwrap();
+        assert_eq!(wallet.active_weight, 6);
+    }
+
+    #[test]
+    fn guardian_weight_change_cannot_drop_below_weight_threshold() {
+        let mut wallet = wallet(3, 2, 0);
+        wallet.weight_threshold = 3;
+        assert_eq!(
+            wallet.on_guardian_weight_changed(&guardian(2, true), 1).err(),
+            Some(WalletError::InvalidThreshold.into())
+        );
+        assert_eq!(wallet.active_weight, 3);
+    }
+
+    #[test]
+    fn inactive_guardian_weight_change_keeps_active_weight() {
+        let mut wallet = wallet(3, 2, 0);
+        wallet.on_guardian_weight_changed(&guardian(1, false), 5).unwrap();
+        assert_eq!(wallet.active_weight, 3);
+    }
+
+    #[test]
+    fn recovery_deactivation_lowers_unreachable_thresholds() {
+        let mut wallet = wallet(3, 3, 3);
+        wallet.recovery_threshold = 3;
+        wallet.weight_threshold = 3;
+        wallet.on_guardian_deactivated_by_recovery(&guardian(1, true));
+        assert_eq!(wallet.active_guardian_count, 2);
+        assert_eq!(wallet.active_weight, 2);
+        assert_eq!(wallet.threshold, 2);
+        assert_eq!(wallet.rejection_threshold, 2);
+        assert_eq!(wallet.recovery_threshold, 2);
+        assert_eq!(wallet.weight_threshold, 2);
+    }
+
+    #[test]
+    fn recovery_deactivation_keeps_reachable_thresholds() {
+        let mut wallet = wallet(4, 2, 0);
+        wallet.weight_threshold = 2;
+        wallet.on_guardian_deactivated_by_recovery(&guardian(1, true));
+        assert_eq!(wallet.active_guardian_count, 3);
+        assert_eq!(wallet.threshold, 2);
+        assert_eq!(wallet.rejection_threshold, 0);
+        assert_eq!(wallet.recovery_threshold, 1);
+        assert_eq!(wallet.weight_threshold, 2);
+    }
+}
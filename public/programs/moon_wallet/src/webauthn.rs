@@ -0,0 +1,395 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked,
+    load_instruction_at_checked,
+};
+use crate::errors::*;
+
+pub const SECP256R1_PROGRAM_ID: Pubkey = pubkey!("Secp256r1SigVerify1111111111111111111111111");
+
+const SIGNATURE_OFFSETS_START: usize = 2;
+const SIGNATURE_OFFSETS_SERIALIZED_SIZE: usize = 14;
+const SIGNATURE_SERIALIZED_SIZE: usize = 64;
+const COMPRESSED_PUBKEY_SERIALIZED_SIZE: usize = 33;
+
+// u16::MAX trong các trường instruction index nghĩa là "chính instruction này"
+const CURRENT_INSTRUCTION_INDEX: u16 = u16::MAX;
+
+// authenticatorData = rpIdHash (32) || flags (1) || signCount (4) || ...
+const AUTHENTICATOR_DATA_MIN_LEN: usize = 37;
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+
+const CLIENT_DATA_TYPE_GET: &[u8] = b"webauthn.get";
+
+/// Dữ liệu assertion do authenticator trả về khi ký bằng passkey
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct WebAuthnAssertion {
+    pub authenticator_data: Vec<u8>,
+    pub client_data_json: Vec<u8>,
+}
+
+/// Bố cục offsets của precompile secp256r1, mỗi chữ ký chiếm 14 bytes sau header 2 bytes
+pub struct Secp256r1SignatureOffsets {
+    pub signature_offset: u16,
+    pub signature_instruction_index: u16,
+    pub public_key_offset: u16,
+    pub public_key_instruction_index: u16,
+    pub message_data_offset: u16,
+    pub message_data_size: u16,
+    pub message_instruction_index: u16,
+}
+
+impl Secp256r1SignatureOffsets {
+    pub fn parse(data: &[u8], signature_index: usize) -> Result<Self> {
+        let start = SIGNATURE_OFFSETS_START + signature_index * SIGNATURE_OFFSETS_SERIALIZED_SIZE;
+        let end = start + SIGNATURE_OFFSETS_SERIALIZED_SIZE;
+        require!(data.len() >= end, WalletError::InvalidInstructionData);
+
+        let read_u16 = |pos: usize| u16::from_le_bytes([data[start + pos], data[start + pos + 1]]);
+
+        Ok(Self {
+            signature_offset: read_u16(0),
+            signature_instruction_index: read_u16(2),
+            public_key_offset: read_u16(4),
+            public_key_instruction_index: read_u16(6),
+            message_data_offset: read_u16(8),
+            message_data_size: read_u16(10),
+            message_instruction_index: read_u16(12),
+        })
+    }
+
+    /// Mọi offset phải trỏ vào chính instruction precompile đang được đọc
+    fn points_into(&self, precompile_index: u16) -> bool {
+        [
+            self.signature_instruction_index,
+            self.public_key_instruction_index,
+            self.message_instruction_index,
+        ]
+        .iter()
+        .all(|index| *index == CURRENT_INSTRUCTION_INDEX || *index == precompile_index)
+    }
+}
+
+fn slice_at(data: &[u8], offset: u16, len: usize) -> Result<&[u8]> {
+    let start = offset as usize;
+    let end = start.checked_add(len).ok_or(WalletError::InvalidInstructionData)?;
+    data.get(start..end).ok_or_else(|| WalletError::InvalidInstructionData.into())
+}
+
+enum SignatureMatch {
+    Verified,
+    WrongMessage,
+    WrongPublicKey,
+}
+
+fn match_precompile_data(
+    data: &[u8],
+    precompile_index: u16,
+    pubkey: &[u8; 33],
+    message: &[u8],
+) -> Result<SignatureMatch> {
+    require!(data.len() >= SIGNATURE_OFFSETS_START, WalletError::InvalidInstructionData);
+
+    let num_signatures = data[0] as usize;
+    require!(num_signatures > 0, WalletError::InvalidSignatureCount);
+
+    let mut result = SignatureMatch::WrongPublicKey;
+    for signature_index in 0..num_signatures {
+        let offsets = Secp256r1SignatureOffsets::parse(data, signature_index)?;
+        require!(
+            offsets.points_into(precompile_index),
+            WalletError::InvalidInstructionData
+        );
+
+        slice_at(data, offsets.signature_offset, SIGNATURE_SERIALIZED_SIZE)?;
+        let pk_in_ix = slice_at(data, offsets.public_key_offset, COMPRESSED_PUBKEY_SERIALIZED_SIZE)?;
+        let signed_message = slice_at(data, offsets.message_data_offset, offsets.message_data_size as usize)?;
+
+        if pk_in_ix != pubkey.as_slice() {
+            continue;
+        }
+        if signed_message == message {
+            return Ok(SignatureMatch::Verified);
+        }
+        result = SignatureMatch::WrongMessage;
+    }
+
+    Ok(result)
+}
+
+/// Tìm trong transaction một instruction secp256r1 đã xác thực `message` bằng `pubkey`.
+/// Precompile có thể nằm ở bất kỳ vị trí nào ngoài instruction hiện tại.
+pub fn verify_secp256r1_signature(
+    instruction_sysvar: &AccountInfo,
+    pubkey: &[u8; 33],
+    message: &[u8],
+) -> Result<()> {
+    require!(
+        !instruction_sysvar.data_is_empty(),
+        WalletError::InstructionMissing
+    );
+
+    let num_instructions = {
+        let data = instruction_sysvar.try_borrow_data()?;
+        require!(data.len() >= 2, WalletError::InstructionMissing);
+        u16::from_le_bytes([data[0], data[1]])
+    };
+    let current_index = load_current_index_checked(instruction_sysvar)?;
+
+    let mut error = WalletError::InstructionMissing;
+    for index in 0..num_instructions {
+        if index == current_index {
+            continue;
+        }
+
+        let ix = load_instruction_at_checked(index as usize, instruction_sysvar)?;
+        if ix.program_id != SECP256R1_PROGRAM_ID {
+            continue;
+        }
+
+        match match_precompile_data(&ix.data, index, pubkey, message)? {
+            SignatureMatch::Verified => return Ok(()),
+            SignatureMatch::WrongMessage => error = WalletError::MessageMismatch,
+            SignatureMatch::WrongPublicKey => {
+                if matches!(error, WalletError::InstructionMissing) {
+                    error = WalletError::PublicKeyMismatch;
+                }
+            }
+        }
+    }
+
+    Err(error.into())
+}
+
+/// Xác thực một WebAuthn assertion cho `expected_message`.
+///
+/// Passkey ký `authenticatorData || sha256(clientDataJSON)`, trong đó challenge của
+/// clientDataJSON là base64url(sha256(expected_message)). `sign_count` được cập nhật
+/// theo bộ đếm của authenticator.
+pub fn verify_webauthn_assertion(
+    instruction_sysvar: &AccountInfo,
+    pubkey: &[u8; 33],
+    rp_id_hash: &[u8; 32],
+    sign_count: &mut u32,
+    assertion: &WebAuthnAssertion,
+    expected_message: &[u8],
+) -> Result<()> {
+    let authenticator_data = &assertion.authenticator_data;
+    require!(
+        authenticator_data.len() >= AUTHENTICATOR_DATA_MIN_LEN,
+        WalletError::InvalidAuthenticatorData
+    );
+    require!(
+        authenticator_data[..32] == rp_id_hash[..],
+        WalletError::RpIdMismatch
+    );
+
+    let flags = authenticator_data[32];
+    require!(
+        flags & FLAG_USER_PRESENT != 0 && flags & FLAG_USER_VERIFIED != 0,
+        WalletError::UserNotVerified
+    );
+
+    let counter = u32::from_be_bytes([
+        authenticator_data[33],
+        authenticator_data[34],
+        authenticator_data[35],
+        authenticator_data[36],
+    ]);
+    check_sign_count(counter, *sign_count)?;
+
+    verify_client_data(&assertion.client_data_json, expected_message)?;
+
+    let client_data_hash = hash(&assertion.client_data_json).to_bytes();
+    let mut signed_data = Vec::with_capacity(authenticator_data.len() + client_data_hash.len());
+    signed_data.extend_from_slice(authenticator_data);
+    signed_data.extend_from_slice(&client_data_hash);
+
+    verify_secp256r1_signature(instruction_sysvar, pubkey, &signed_data)?;
+
+    *sign_count = counter;
+    Ok(())
+}
+
+// Authenticator không hỗ trợ bộ đếm luôn trả về 0
+fn check_sign_count(counter: u32, stored: u32) -> Result<()> {
+    if counter != 0 || stored != 0 {
+        require!(counter > stored, WalletError::SignCountReplay);
+    }
+    Ok(())
+}
+
+fn verify_client_data(client_data_json: &[u8], expected_message: &[u8]) -> Result<()> {
+    let client_data_type = json_string_field(client_data_json, "type")
+        .ok_or(WalletError::InvalidClientData)?;
+    require!(
+        client_data_type == CLIENT_DATA_TYPE_GET,
+        WalletError::InvalidClientData
+    );
+
+    let challenge = json_string_field(client_data_json, "challenge")
+        .ok_or(WalletError::InvalidClientData)?;
+    let expected_challenge = base64url_encode(&hash(expected_message).to_bytes());
+    require!(
+        challenge == expected_challenge.as_bytes(),
+        WalletError::ChallengeMismatch
+    );
+
+    Ok(())
+}
+
+/// Lấy giá trị chuỗi của một khoá cấp cao nhất trong clientDataJSON.
+/// Trình duyệt serialize clientDataJSON không có khoảng trắng và không escape các trường này.
+fn json_string_field<'a>(json: &'a [u8], key: &str) -> Option<&'a [u8]> {
+    let pattern = format!("\"{}\":\"", key);
+    let pattern = pattern.as_bytes();
+    let start = json
+        .windows(pattern.len())
+        .position(|window| window == pattern)?
+        + pattern.len();
+    let len = json[start..].iter().position(|byte| *byte == b'"')?;
+    Some(&json[start..start + len])
+}
+
+fn base64url_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+    let mut result = String::with_capacity((data.len() * 4).div_ceil(3));
+    for chunk in data.chunks(3) {
+        let b0 = chunk[0] as u32;
+        let b1 = chunk.get(1).copied().unwrap_or(0) as u32;
+        let b2 = chunk.get(2).copied().unwrap_or(0) as u32;
+        let triple = (b0 << 16) | (b1 << 8) | b2;
+
+        result.push(ALPHABET[(triple >> 18) as usize & 0x3f] as char);
+        result.push(ALPHABET[(triple >> 12) as usize & 0x3f] as char);
+        if chunk.len() > 1 {
+            result.push(ALPHABET[(triple >> 6) as usize & 0x3f] as char);
+        }
+        if chunk.len() > 2 {
+            result.push(ALPHABET[triple as usize & 0x3f] as char);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBKEY: [u8; 33] = [2; 33];
+
+    /// Instruction data precompile với một chữ ký: header || offsets || pubkey || signature || message
+    fn precompile_data(pubkey: &[u8; 33], message: &[u8], instruction_index: u16) -> Vec<u8> {
+        let public_key_offset = (SIGNATURE_OFFSETS_START + SIGNATURE_OFFSETS_SERIALIZED_SIZE) as u16;
+        let signature_offset = public_key_offset + COMPRESSED_PUBKEY_SERIALIZED_SIZE as u16;
+        let message_data_offset = signature_offset + SIGNATURE_SERIALIZED_SIZE as u16;
+
+        let mut data = vec![1, 0];
+        for value in [
+            signature_offset,
+            instruction_index,
+            public_key_offset,
+            instruction_index,
+            message_data_offset,
+            message.len() as u16,
+            instruction_index,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(pubkey);
+        data.extend_from_slice(&[0; SIGNATURE_SERIALIZED_SIZE]);
+        data.extend_from_slice(message);
+        data
+    }
+
+    #[test]
+    fn parse_reads_offsets_of_each_signature() {
+        let data = precompile_data(&PUBKEY, b"message", CURRENT_INSTRUCTION_INDEX);
+        let offsets = Secp256r1SignatureOffsets::parse(&data, 0).unwrap();
+        assert_eq!(offsets.public_key_offset, 16);
+        assert_eq!(offsets.signature_offset, 49);
+        assert_eq!(offsets.message_data_offset, 113);
+        assert_eq!(offsets.message_data_size, 7);
+        assert_eq!(offsets.message_instruction_index, CURRENT_INSTRUCTION_INDEX);
+
+        assert!(Secp256r1SignatureOffsets::parse(&data[..15], 0).is_err());
+        assert!(Secp256r1SignatureOffsets::parse(&data[..16], 1).is_err());
+    }
+
+    #[test]
+    fn match_precompile_data_verifies_matching_signature() {
+        for index in [CURRENT_INSTRUCTION_INDEX, 3] {
+            let data = precompile_data(&PUBKEY, b"message", index);
+            let result = match_precompile_data(&data, 3, &PUBKEY, b"message").unwrap();
+            assert!(matches!(result, SignatureMatch::Verified));
+        }
+    }
+
+    #[test]
+    fn match_precompile_data_rejects_other_instruction_index() {
+        let data = precompile_data(&PUBKEY, b"message", 1);
+        let result = match_precompile_data(&data, 3, &PUBKEY, b"message");
+        assert_eq!(result.err(), Some(WalletError::InvalidInstructionData.into()));
+    }
+
+    #[test]
+    fn match_precompile_data_rejects_out_of_bounds_offsets() {
+        let mut data = precompile_data(&PUBKEY, b"message", CURRENT_INSTRUCTION_INDEX);
+        // message_data_size vượt quá độ dài instruction
+        data[12..14].copy_from_slice(&u16::MAX.to_le_bytes());
+        let result = match_precompile_data(&data, 0, &PUBKEY, b"message");
+        assert_eq!(result.err(), Some(WalletError::InvalidInstructionData.into()));
+
+        let data = precompile_data(&PUBKEY, b"message", CURRENT_INSTRUCTION_INDEX);
+        let result = match_precompile_data(&data[..data.len() - 1], 0, &PUBKEY, b"message");
+        assert_eq!(result.err(), Some(WalletError::InvalidInstructionData.into()));
+    }
+
+    #[test]
+    fn match_precompile_data_reports_pubkey_and_message_mismatch() {
+        let data = precompile_data(&PUBKEY, b"message", CURRENT_INSTRUCTION_INDEX);
+
+        let result = match_precompile_data(&data, 0, &[3; 33], b"message").unwrap();
+        assert!(matches!(result, SignatureMatch::WrongPublicKey));
+
+        let result = match_precompile_data(&data, 0, &PUBKEY, b"other").unwrap();
+        assert!(matches!(result, SignatureMatch::WrongMessage));
+    }
+
+    #[test]
+    fn match_precompile_data_requires_a_signature() {
+        let mut data = precompile_data(&PUBKEY, b"message", CURRENT_INSTRUCTION_INDEX);
+        data[0] = 0;
+        let result = match_precompile_data(&data, 0, &PUBKEY, b"message");
+        assert_eq!(result.err(), Some(WalletError::InvalidSignatureCount.into()));
+    }
+
+    #[test]
+    fn json_string_field_reads_top_level_strings() {
+        let json = br#"{"type":"webauthn.get","challenge":"abc-_","origin":"https://moon.app"}"#;
+        assert_eq!(json_string_field(json, "type"), Some(&b"webauthn.get"[..]));
+        assert_eq!(json_string_field(json, "challenge"), Some(&b"abc-_"[..]));
+        assert_eq!(json_string_field(json, "crossOrigin"), None);
+        assert_eq!(json_string_field(br#"{"type":"webauthn.get"#, "type"), None);
+    }
+
+    #[test]
+    fn base64url_encode_has_no_padding() {
+        assert_eq!(base64url_encode(b""), "");
+        assert_eq!(base64url_encode(b"f"), "Zg");
+        assert_eq!(base64url_encode(b"fo"), "Zm8");
+        assert_eq!(base64url_encode(b"foo"), "Zm9v");
+        assert_eq!(base64url_encode(&[0xfb, 0xff]), "-_8");
+    }
+
+    #[test]
+    fn sign_count_must_increase_unless_unsupported() {
+        assert!(check_sign_count(0, 0).is_ok());
+        assert!(check_sign_count(5, 4).is_ok());
+        assert_eq!(check_sign_count(4, 4).err(), Some(WalletError::SignCountReplay.into()));
+        assert_eq!(check_sign_count(0, 4).err(), Some(WalletError::SignCountReplay.into()));
+    }
+}
@@ -6,6 +6,12 @@ import { createFeePayerKeypair } from "@/lib/solana/keypairs";
 import { createInitializeMultisigTx } from "@/lib/solana/transactions";
 import { saveWalletMetadata } from "@/lib/firebase/walletService";
 
+// Cấu hình mặc định cho ví mới: đề xuất hết hạn sau 7 ngày,
+// khôi phục cần 1 guardian phê duyệt và chờ 2 ngày trước khi hoàn tất
+const DEFAULT_PROPOSAL_TTL = 7 * 24 * 60 * 60;
+const DEFAULT_RECOVERY_DELAY = 2 * 24 * 60 * 60;
+const DEFAULT_RECOVERY_THRESHOLD = 1;
+
 // Khởi tạo kết nối và fee payer
 const feePayer = createFeePayerKeypair();
 
@@ -13,9 +19,9 @@ export async function POST(req: Request) {
   try {
     console.log("Current RPC endpoint:", connection.rpcEndpoint);
     console.log("feePayer:", feePayer.publicKey);
-    const { threshold, credentialId, name, multisigPDA } = await req.json();
+    const { threshold, credentialId, rpId, name, multisigPDA } = await req.json();
 
-    if (!threshold || !credentialId || !multisigPDA) {
+    if (!threshold || !credentialId || !rpId || !multisigPDA) {
       return NextResponse.json(
         { error: "Missing required fields" },
         { status: 400 },
@@ -25,7 +31,14 @@ export async function POST(req: Request) {
     const multisigPubkey = new PublicKey(multisigPDA);
     const transaction = await createInitializeMultisigTx(
       program,
-      { threshold, credentialId },
+      {
+        threshold,
+        credentialId,
+        rpId,
+        proposalTtl: DEFAULT_PROPOSAL_TTL,
+        recoveryDelay: DEFAULT_RECOVERY_DELAY,
+        recoveryThreshold: DEFAULT_RECOVERY_THRESHOLD,
+      },
       multisigPubkey,
       feePayer,
     );
@@ -1,134 +1,122 @@
 import { NextResponse } from 'next/server';
-import { PublicKey, Keypair, Transaction, TransactionInstruction } from '@solana/web3.js';
-import { connection } from '@/lib/solana';
-import { getGuardianPDA } from '@/utils/credentialUtils';
-import { PROGRAM_ID } from '@/utils/constants';
+import { PublicKey, Transaction } from '@solana/web3.js';
+import { connection, program } from '@/lib/solana';
+import { createFeePayerKeypair } from '@/lib/solana/keypairs';
+import { createFinalizeRecoveryTx } from '@/lib/solana/transactions';
+import { getGuardianPDA, getRecoveryRequestPDA } from '@/utils/credentialUtils';
+import { compressPublicKey } from '@/utils/bufferUtils';
 import { saveWebAuthnCredentialMapping } from '@/lib/firebase/webAuthnService';
 import { Buffer } from 'buffer';
 
+// Khôi phục gồm nhiều bước: guardian có quyền khôi phục tạo yêu cầu và các guardian phê duyệt
+// bằng passkey của họ (initiate_recovery/approve_recovery, ký ở phía client, xem recoverySigning.ts).
+// Sau thời gian chờ bất kỳ ai cũng có thể hoàn tất, nên bước finalize chạy ở đây với fee payer
+const feePayer = createFeePayerKeypair();
+
 export async function POST(req: Request) {
   try {
-    const {
-      multisigPDA,
-      oldGuardianId,
-      newGuardianId,
-      recoveryPhrase,
-      webauthnCredentialId,
-      webauthnPublicKey
-    } = await req.json();
-    
-    if (!multisigPDA || !oldGuardianId || !newGuardianId || !recoveryPhrase || !webauthnCredentialId || !webauthnPublicKey) {
-      return NextResponse.json(
-        { error: "Thiếu thông tin cần thiết cho quá trình khôi phục" },
-        { status: 400 }
-      );
-    }
-    
-    console.log("Nhận yêu cầu khôi phục quyền truy cập với thông tin:", {
-      multisigPDA: multisigPDA,
-      oldGuardianId: oldGuardianId,
-      newGuardianId: newGuardianId,
-      recoveryPhraseLength: recoveryPhrase.length,
-      webauthnCredentialId: webauthnCredentialId,
-      webauthnPublicKeyLength: webauthnPublicKey.length
-    });
-    
-    // Tạo keypair từ fee payer secret key
-    let feePayerKeypair;
-    try {
-      const feePayerSecretStr = process.env.NEXT_PUBLIC_FEE_PAYER_SECRET_KEY as string;
-      const secretKeyArray = feePayerSecretStr.split(',').map(Number);
-      const secretKey = new Uint8Array(secretKeyArray);
-      feePayerKeypair = Keypair.fromSecretKey(secretKey);
-      console.log("Fee Payer public key:", feePayerKeypair.publicKey.toString());
-    } catch (keypairError) {
-      console.error("Lỗi khi tạo keypair từ secret key:", keypairError);
-      return NextResponse.json(
-        { error: "Không thể tạo keypair cho fee payer" },
-        { status: 500 }
-      );
+    const body = await req.json();
+    const action = body.action ?? 'finalize';
+
+    if (action === 'finalize') {
+      return await finalizeRecovery(body);
     }
-    
-    // Lấy PDA cho guardian mới và cũ
-    const multisigPubkey = new PublicKey(multisigPDA);
-    const oldGuardianPDA = getGuardianPDA(multisigPubkey, oldGuardianId);
-    const newGuardianPDA = getGuardianPDA(multisigPubkey, newGuardianId);
-    
-    console.log("Old Guardian PDA:", oldGuardianPDA.toString());
-    console.log("New Guardian PDA:", newGuardianPDA.toString());
-    
-    // Chuyển đổi recovery phrase thành Uint8Array
-    const recoveryPhraseArray = Uint8Array.from(recoveryPhrase);
-    
-    // Chuyển đổi webauthn public key thành Uint8Array
-    const publicKeyArray = Uint8Array.from(webauthnPublicKey);
-    
-    // Tạo discriminator cho recover_access_by_guardian
-    const recoverAccessDiscriminator = Buffer.from([210, 31, 244, 215, 121, 93, 165, 99]);
-    
-    // Tạo dữ liệu cho tham số
-    const oldGuardianIdBuffer = Buffer.alloc(8);
-    oldGuardianIdBuffer.writeBigUInt64LE(BigInt(oldGuardianId), 0);
-    
-    const newGuardianIdBuffer = Buffer.alloc(8);
-    newGuardianIdBuffer.writeBigUInt64LE(BigInt(newGuardianId), 0);
-    
-    // Tạo data instruction
-    const recoverData = Buffer.concat([
-      recoverAccessDiscriminator,
-      oldGuardianIdBuffer,
-      newGuardianIdBuffer,
-      Buffer.from(recoveryPhraseArray),
-      Buffer.from(publicKeyArray)
-    ]);
-    
-    // Tạo và gửi transaction
-    const transaction = new Transaction();
-    
-    // Tạo instruction khôi phục quyền truy cập
-    const recoverInstruction = new TransactionInstruction({
-      keys: [
-        { pubkey: multisigPubkey, isSigner: false, isWritable: true },
-        { pubkey: oldGuardianPDA, isSigner: false, isWritable: true },
-        { pubkey: feePayerKeypair.publicKey, isSigner: false, isWritable: false },
-        { pubkey: newGuardianPDA, isSigner: false, isWritable: true },
-        { pubkey: feePayerKeypair.publicKey, isSigner: false, isWritable: false },
-        { pubkey: PublicKey.default, isSigner: false, isWritable: false },
-      ],
-      programId: PROGRAM_ID,
-      data: recoverData,
-    });
-    
-    transaction.add(recoverInstruction);
-    transaction.feePayer = feePayerKeypair.publicKey;
-    transaction.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
-    transaction.sign(feePayerKeypair);
-    
-    console.log("Gửi transaction...");
-    const signature = await connection.sendRawTransaction(
-      transaction.serialize(),
-      { skipPreflight: false }
+
+    return NextResponse.json(
+      { error: `Hành động khôi phục không hợp lệ: ${action}` },
+      { status: 400 }
     );
-    
-    console.log("Transaction đã gửi, signature:", signature);
-    
-    // Đợi xác nhận với phương thức mới
-    const latestBlockhash = await connection.getLatestBlockhash('confirmed');
-    await connection.confirmTransaction({
-      blockhash: latestBlockhash.blockhash,
-      lastValidBlockHeight: latestBlockhash.lastValidBlockHeight,
-      signature
-    }, 'confirmed');
-    
-    console.log("Transaction đã được xác nhận");
-    
-    // Cập nhật Firebase
+  } catch (error) {
+    console.error("Lỗi khi khôi phục quyền truy cập:", error);
+    return NextResponse.json(
+      {
+        error: error instanceof Error ? error.message : "Lỗi khi khôi phục quyền truy cập"
+      },
+      { status: 500 }
+    );
+  }
+}
+
+async function fetchRecoveryNonce(multisigPubkey: PublicKey): Promise<number> {
+  const multisigInfo = await connection.getAccountInfo(multisigPubkey);
+  if (!multisigInfo) {
+    throw new Error(`Không tìm thấy ví multisig: ${multisigPubkey.toString()}`);
+  }
+  const multisig = program.coder.accounts.decode("multiSigWallet", multisigInfo.data);
+  return Number(multisig.recoveryNonce);
+}
+
+async function sendAndConfirm(transaction: Transaction): Promise<string> {
+  const latestBlockhash = await connection.getLatestBlockhash('confirmed');
+  transaction.feePayer = feePayer.publicKey;
+  transaction.recentBlockhash = latestBlockhash.blockhash;
+  transaction.sign(feePayer);
+
+  console.log("Gửi transaction...");
+  const signature = await connection.sendRawTransaction(
+    transaction.serialize(),
+    { skipPreflight: false }
+  );
+  console.log("Transaction đã gửi, signature:", signature);
+
+  await connection.confirmTransaction({
+    blockhash: latestBlockhash.blockhash,
+    lastValidBlockHeight: latestBlockhash.lastValidBlockHeight,
+    signature
+  }, 'confirmed');
+  console.log("Transaction đã được xác nhận");
+
+  return signature;
+}
+
+async function finalizeRecovery(body: any) {
+  const { multisigPDA, webauthnCredentialId, webauthnPublicKey } = body;
+
+  if (!multisigPDA) {
+    return NextResponse.json(
+      { error: "Thiếu địa chỉ ví cần khôi phục" },
+      { status: 400 }
+    );
+  }
+
+  const multisigPubkey = new PublicKey(multisigPDA);
+  const recoveryNonce = await fetchRecoveryNonce(multisigPubkey);
+  const recoveryRequestPDA = getRecoveryRequestPDA(multisigPubkey, recoveryNonce);
+
+  const requestInfo = await connection.getAccountInfo(recoveryRequestPDA);
+  if (!requestInfo) {
+    return NextResponse.json(
+      { error: "Ví không có yêu cầu khôi phục nào đang chờ" },
+      { status: 404 }
+    );
+  }
+  const request = program.coder.accounts.decode("recoveryRequest", requestInfo.data);
+
+  // Guardian đã bị xoá sau khi phê duyệt không còn tài khoản nên không được truyền vào
+  const approverPDAs: PublicKey[] = request.approvers.map((id: any) =>
+    getGuardianPDA(multisigPubkey, Number(id))
+  );
+  const approverInfos = await connection.getMultipleAccountsInfo(approverPDAs);
+  const existingApproverPDAs = approverPDAs.filter((_, i) => approverInfos[i] !== null);
+
+  const transaction = await createFinalizeRecoveryTx(
+    program,
+    multisigPubkey,
+    recoveryRequestPDA,
+    request,
+    existingApproverPDAs
+  );
+  const signature = await sendAndConfirm(transaction);
+
+  // Chỉ ánh xạ credential mới sau khi khoá đã thực sự được xoay trên chuỗi
+  if (webauthnCredentialId && webauthnPublicKey) {
     try {
+      const compressedKey = compressPublicKey(Buffer.from(webauthnPublicKey, 'hex'));
       await saveWebAuthnCredentialMapping(
         webauthnCredentialId,
         multisigPDA,
-        Array.from(publicKeyArray),
-        newGuardianId,
+        Array.from(compressedKey),
+        Number(request.newGuardianId),
         undefined, // guardianName không thay đổi
         undefined, // threshold không thay đổi
         true // isOwner
@@ -138,19 +126,11 @@ export async function POST(req: Request) {
       console.error("Lỗi khi cập nhật cơ sở dữ liệu:", dbError);
       // Vẫn tiếp tục vì giao dịch đã thành công trên blockchain
     }
-    
-    return NextResponse.json({
-      success: true,
-      signature,
-      message: "Khôi phục quyền truy cập thành công"
-    });
-  } catch (error) {
-    console.error("Lỗi khi khôi phục quyền truy cập:", error);
-    return NextResponse.json(
-      { 
-        error: error instanceof Error ? error.message : "Lỗi khi khôi phục quyền truy cập" 
-      },
-      { status: 500 }
-    );
   }
-} 
\ No newline at end of file
+
+  return NextResponse.json({
+    success: true,
+    signature,
+    message: "Khôi phục quyền truy cập thành công"
+  });
+}
@@ -7,6 +7,10 @@ import { Card, CardContent, CardHeader, CardTitle, CardDescription } from "@/com
 import { searchGuardiansByUsername } from "@/lib/firebase/guardianService"; 
 import { hashRecoveryPhrase } from "@/utils/guardianUtils";
 import { createWebAuthnCredential } from "@/utils/webauthnUtils";
+import { handleInitiateRecovery } from "@/utils/recoverySigning";
+import { connection, program } from "@/lib/solana";
+import { createFeePayerKeypair } from "@/lib/solana/keypairs";
+import { Buffer } from "buffer";
 import { GuardianData } from "@/types/guardian";
 import { useRouter } from "next/navigation";
 import { Loader2, CheckCircle, User, KeySquare, ArrowLeft, ArrowRight, Shield } from "lucide-react";
@@ -25,6 +29,12 @@ export function RecoveryForm({ currentStep, onStepChange }: RecoveryFormProps) {
   const [error, setError] = useState("");
   const [selectedGuardian, setSelectedGuardian] = useState<GuardianData | null>(null);
   const [isSuccess, setIsSuccess] = useState(false);
+  const [pendingRecovery, setPendingRecovery] = useState<{
+    credentialId: string;
+    publicKey: string;
+    executableAt?: number;
+    requiredApprovals?: number;
+  } | null>(null);
   
   // Hàm tìm kiếm guardian theo username và xác thực recovery phrase (Bước 1)
   const handleSearch = async () => {
@@ -79,29 +89,70 @@ export function RecoveryForm({ currentStep, onStepChange }: RecoveryFormProps) {
       // 2. Hash recovery phrase
       const hashedRecoveryBytes = await hashRecoveryPhrase(recoveryPhrase);
       
-      // 3. Gọi API để thực hiện khôi phục
+      // 3. Guardian ký bằng passkey hiện tại để tạo yêu cầu khôi phục,
+      //    owner cũ được xác định qua recovery phrase
+      const { signature, recoveryRequest } = await handleInitiateRecovery(
+        connection,
+        selectedGuardian.multisigPDA,
+        selectedGuardian.guardianId,
+        Uint8Array.from(hashedRecoveryBytes),
+        Buffer.from(webAuthnResult.publicKey, "hex"),
+        createFeePayerKeypair(),
+        selectedGuardian.webauthnCredentialId
+      );
+      const latestBlockhash = await connection.getLatestBlockhash("confirmed");
+      await connection.confirmTransaction({ signature, ...latestBlockhash }, "confirmed");
+      
+      // Credential mới chỉ dùng được sau khi yêu cầu được hoàn tất
+      const requestInfo = await connection.getAccountInfo(recoveryRequest, "confirmed");
+      const request = requestInfo
+        ? program.coder.accounts.decode("recoveryRequest", requestInfo.data)
+        : null;
+      setPendingRecovery({
+        credentialId: webAuthnResult.credentialId,
+        publicKey: webAuthnResult.publicKey,
+        executableAt: request ? Number(request.executableAt) : undefined,
+        requiredApprovals: request?.requiredApprovals,
+      });
+    } catch (err) {
+      setError(err instanceof Error ? err.message : "Lỗi khi khôi phục");
+    } finally {
+      setIsLoading(false);
+    }
+  };
+  
+  // Hoàn tất yêu cầu khôi phục sau khi đủ phê duyệt và hết thời gian chờ (Bước 3)
+  const handleFinalize = async () => {
+    try {
+      setIsLoading(true);
+      setError("");
+      
+      if (!selectedGuardian || !pendingRecovery) {
+        setError("Không có yêu cầu khôi phục đang chờ");
+        return;
+      }
+      
       const response = await fetch("/api/wallet/recover", {
         method: "POST",
         headers: { "Content-Type": "application/json" },
         body: JSON.stringify({
+          action: "finalize",
           multisigPDA: selectedGuardian.multisigPDA,
-          oldGuardianId: selectedGuardian.guardianId,
-          newGuardianId: selectedGuardian.guardianId, // Sử dụng cùng ID
-          recoveryPhrase: Array.from(hashedRecoveryBytes),
-          webauthnCredentialId: webAuthnResult.credentialId,
-          webauthnPublicKey: webAuthnResult.publicKey
+          webauthnCredentialId: pendingRecovery.credentialId,
+          webauthnPublicKey: pendingRecovery.publicKey
         }),
       });
       
       if (!response.ok) {
         const data = await response.json();
-        throw new Error(data.error ?? "Lỗi khi khôi phục quyền truy cập");
+        throw new Error(data.error ?? "Lỗi khi hoàn tất khôi phục");
       }
       
       // Lưu thông tin credential vào localStorage
-      localStorage.setItem('current_credential_id', webAuthnResult.credentialId);
+      localStorage.setItem('current_credential_id', pendingRecovery.credentialId);
       localStorage.setItem('current_guardian_id', selectedGuardian.guardianId.toString());
       
+      setPendingRecovery(null);
       setIsSuccess(true);
     } catch (err) {
       setError(err instanceof Error ? err.message : "Lỗi khi khôi phục");
@@ -196,7 +247,7 @@ export function RecoveryForm({ currentStep, onStepChange }: RecoveryFormProps) {
           </motion.div>
         )}
         
-        {currentStep === "create" && !isSuccess && (
+        {currentStep === "create" && !isSuccess && !pendingRecovery && (
           <motion.div 
             className="space-y-5"
             initial={{ opacity: 0, y: 20 }}
@@ -257,6 +308,43 @@ export function RecoveryForm({ currentStep, onStepChange }: RecoveryFormProps) {
           </motion.div>
         )}
         
+        {currentStep === "create" && pendingRecovery && (
+          <motion.div 
+            className="space-y-5 text-center py-4"
+            initial={{ opacity: 0, scale: 0.9 }}
+            animate={{ opacity: 1, scale: 1 }}
+            transition={{ duration: 0.5 }}
+          >
+            <div>
+              <h3 className="text-xl font-semibold text-blue-200 mb-2">Đã tạo yêu cầu khôi phục</h3>
+              <p className="text-gray-300 text-sm">
+                Cần {pendingRecovery.requiredApprovals ?? "đủ"} guardian phê duyệt yêu cầu.
+                {pendingRecovery.executableAt && (
+                  <> Có thể hoàn tất sau {new Date(pendingRecovery.executableAt * 1000).toLocaleString()}.</>
+                )}
+              </p>
+            </div>
+            
+            <Button 
+              onClick={handleFinalize}
+              disabled={isLoading}
+              className="w-full gradient-cosmic hover:opacity-90 shadow-lg"
+            >
+              {isLoading ? (
+                <>
+                  <Loader2 className="h-4 w-4 animate-spin mr-2" />
+                  <span>Đang xử lý...</span>
+                </>
+              ) : (
+                <>
+                  <Shield className="h-4 w-4 mr-2" />
+                  <span>Hoàn tất khôi phục</span>
+                </>
+              )}
+            </Button>
+          </motion.div>
+        )}
+        
         {currentStep === "create" && isSuccess && (
           <motion.div 
             className="space-y-5 text-center py-4"
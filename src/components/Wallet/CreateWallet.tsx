@@ -139,6 +139,7 @@ export default function CreateWallet() {
         body: JSON.stringify({
           threshold: validThreshold,
           credentialId: rawIdBase64,
+          rpId: window.location.hostname,
           name: walletName,
           multisigPDA: multisigPDA.toString(),
         }),
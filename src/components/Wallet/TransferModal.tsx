@@ -1,4 +1,4 @@
-import React, { useState, useEffect, useRef } from 'react';
+import React, { useState, useEffect } from 'react';
 import { 
   Modal, 
   ModalContent, 
@@ -18,7 +18,13 @@ import { Buffer } from 'buffer';
 import BN from 'bn.js';
 import { useWalletInfo } from "@/hooks/useWalletInfo";
 import { createProposal as saveProposalToFirebase } from '@/lib/firebase/proposalService';
-import { createSolTransferProposal, createTokenTransferProposal } from '@/utils/transferUtils';
+import {
+  buildSolTransferActions,
+  buildTokenTransferActions,
+  createSolTransferProposal,
+  createTokenTransferProposal,
+  prepareCreateProposal
+} from '@/utils/transferUtils';
 import { getTokenAccounts } from '@/utils/tokenListUtils';
 import { convertToTokenAmount } from '@/utils/tokenUtils';
 import { Card, CardContent, CardHeader, CardTitle } from "@/components/ui/card";
@@ -61,7 +67,6 @@ export function TransferModal({
   const [isLoading, setIsLoading] = useState(false);
   const [error, setError] = useState<string | null>(null);
   const { multisigPDA, threshold } = useWalletInfo();
-  const proposalIdTimestamp = useRef<number>(0);
 
   // State for token list and selected token
   const [assets, setAssets] = useState<AssetOption[]>([]);
@@ -71,8 +76,6 @@ export function TransferModal({
   useEffect(() => {
     if (isOpen && multisigPDA) {
       loadTokens();
-      // Set timestamp for proposal ID when modal opens
-      proposalIdTimestamp.current = Date.now();
     }
   }, [isOpen, multisigPDA]);
 
@@ -191,8 +194,6 @@ export function TransferModal({
       const destinationPublicKey = new PublicKey(destinationAddress);
       const multisigPublicKey = new PublicKey(multisigPDA);
       const guardianPDA = getGuardianPDA(multisigPublicKey, guardianId);
-      // Sử dụng giá trị timestamp đã được lưu trong useRef
-      const proposalId = new BN(proposalIdTimestamp.current);
   
       // Get WebAuthn public key
       const webAuthnPubKey = await getWebAuthnPublicKey(credentialId);
@@ -211,12 +212,23 @@ export function TransferModal({
         throw new Error(`Invalid amount. Current balance: ${assetInfo.balance} ${assetInfo.symbol}`);
       }
 
-      // Create message and get assertion
-      const messageString = assetInfo.type === 'sol'
-        ? `create:proposal_transfer_${amount}_SOL_to_${destinationAddress}`
-        : `create:proposal_token_transfer_${amount}_${assetInfo.symbol}_to_${destinationAddress}`;
+      // Build the proposal actions; the passkey signs their contents hash
+      const amountLamports = new BN(parseFloat(amount) * LAMPORTS_PER_SOL);
+      const tokenAmount = assetInfo.type === 'token'
+        ? convertToTokenAmount(parseFloat(amount), assetInfo.decimals)
+        : new BN(0);
+      const actions = assetInfo.type === 'sol'
+        ? buildSolTransferActions(amountLamports, destinationPublicKey)
+        : buildTokenTransferActions(new PublicKey(assetInfo.mint!), tokenAmount, destinationPublicKey);
+
+      // The program assigns the proposal ID, so read it together with the challenge
+      const { proposalId, challenge } = await prepareCreateProposal(connection, {
+        multisigPubkey: multisigPublicKey,
+        guardianId,
+        actions
+      });
     
-      const assertion = await getWebAuthnAssertion(credentialId, messageString, true);
+      const assertion = await getWebAuthnAssertion(credentialId, challenge, true);
       if (!assertion) {
         throw new Error('Unable to get WebAuthn signature');
       }
@@ -228,7 +240,6 @@ export function TransferModal({
     
       if (assetInfo.type === 'sol') {
         // Transfer SOL
-        const amountLamports = new BN(parseFloat(amount) * LAMPORTS_PER_SOL);
         const tx = await createSolTransferProposal({
           multisigPubkey: multisigPublicKey,
           payerPublicKey: feePayerKeypair.publicKey,
@@ -273,8 +284,6 @@ export function TransferModal({
         console.log('Token Mint:', assetInfo.mint);
         console.log('Token Amount:', amount);
         console.log('Token Decimals:', assetInfo.decimals);
-        
-        const tokenAmount = convertToTokenAmount(parseFloat(amount), assetInfo.decimals);
         console.log('Raw Token Amount:', tokenAmount.toString());
 
         try {
@@ -10,11 +10,16 @@ import {
   TransactionInstruction,
 } from "@solana/web3.js";
 import { PROGRAM_ID } from "./index";
+import { getGuardianPDA, getGuardianRegistryPDA } from "@/utils/credentialUtils";
 
 // Types
 export interface InitializeMultisigParams {
   threshold: number;
   credentialId: string;
+  rpId: string;
+  proposalTtl: number;
+  recoveryDelay: number;
+  recoveryThreshold: number;
 }
 
 export interface AddGuardianParams {
@@ -41,9 +46,17 @@ export const createInitializeMultisigTx = async (
 ): Promise<Transaction> => {
   try {
     return await program.methods
-      .initializeMultisig(params.threshold, params.credentialId)
-      .accounts({
+      .initializeMultisig(
+        params.threshold,
+        params.credentialId,
+        params.rpId,
+        new BN(params.proposalTtl),
+        new BN(params.recoveryDelay),
+        params.recoveryThreshold,
+      )
+      .accountsPartial({
         multisig: multisigPDA,
+        guardianRegistry: getGuardianRegistryPDA(multisigPDA),
         feePayer: feePayer.publicKey,
         systemProgram: SystemProgram.programId,
       })
@@ -268,3 +281,40 @@ export const readMultisigNonce = async (
 };
 
 
+
+/**
+ * Hoàn tất yêu cầu khôi phục khi đã đủ phê duyệt và hết thời gian chờ.
+ * Rent của yêu cầu được trả lại cho người đã tạo nó. approverPDAs là tài khoản guardian
+ * của những người đã phê duyệt và vẫn còn tồn tại, chương trình kiểm tra lại từng người
+ */
+export const createFinalizeRecoveryTx = async (
+  program: Program,
+  multisigPDA: PublicKey,
+  recoveryRequestPDA: PublicKey,
+  recoveryRequest: {
+    initiator: PublicKey;
+    oldGuardianId: BN;
+    newGuardianId: BN;
+  },
+  approverPDAs: PublicKey[],
+): Promise<Transaction> => {
+  try {
+    return await program.methods
+      .finalizeRecovery()
+      .accountsPartial({
+        multisig: multisigPDA,
+        recoveryRequest: recoveryRequestPDA,
+        initiator: recoveryRequest.initiator,
+        oldGuardian: getGuardianPDA(multisigPDA, recoveryRequest.oldGuardianId.toNumber()),
+        newGuardian: getGuardianPDA(multisigPDA, recoveryRequest.newGuardianId.toNumber()),
+        clock: SYSVAR_CLOCK_PUBKEY,
+      })
+      .remainingAccounts(
+        approverPDAs.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false })),
+      )
+      .transaction();
+  } catch (error) {
+    console.error("Error creating finalize recovery transaction:", error);
+    throw error;
+  }
+};
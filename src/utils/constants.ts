@@ -6,5 +6,16 @@ const PROGRAM_ID_STRING = process.env.NEXT_PUBLIC_PROGRAM_ID ?? '6Y3N5AQRQtviTrm
 // Export PROGRAM_ID để có thể sử dụng trong toàn bộ ứng dụng
 export const PROGRAM_ID = new PublicKey(PROGRAM_ID_STRING);
 
+// Cluster của chương trình, phải khớp với feature khi build (0 = localnet, 1 = mainnet, 2 = devnet).
+// Bản build mặc định của chương trình là devnet
+const CLUSTER_IDS: Record<string, number> = { localnet: 0, 'mainnet-beta': 1, devnet: 2 };
+const SOLANA_NETWORK = process.env.NEXT_PUBLIC_SOLANA_NETWORK ?? 'devnet';
+if (!(SOLANA_NETWORK in CLUSTER_IDS)) {
+  throw new Error(
+    `NEXT_PUBLIC_SOLANA_NETWORK không hợp lệ: ${SOLANA_NETWORK} (cần localnet, devnet hoặc mainnet-beta)`,
+  );
+}
+export const CLUSTER_ID = CLUSTER_IDS[SOLANA_NETWORK];
+
 // Program ID của Secp256r1
 export const SECP256R1_PROGRAM_ID = new PublicKey("Secp256r1SigVerify1111111111111111111111111"); 
\ No newline at end of file
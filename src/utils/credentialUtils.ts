@@ -1,37 +1,21 @@
 import { PublicKey } from "@solana/web3.js";
 import { Buffer } from "buffer";
+import { sha256 } from "@noble/hashes/sha256";
 
 import { PROGRAM_ID } from "../lib/solana/index";
 
+/**
+ * Phiên bản của cách sinh seed PDA multisig, phải khớp với MULTISIG_SEED_VERSION trong smart contract
+ */
+export const MULTISIG_SEED_VERSION = 1;
+
 /**
  * Xử lý credential ID để tạo seed cho PDA
- * Cách xử lý này phải khớp với hàm process_credential_id_seed trong smart contract
+ * Cách xử lý này phải khớp với hàm multisig_seed trong smart contract:
+ * seed là sha256 của credential ID nên không còn va chạm giữa các credential khác nhau
  */
 export const processCredentialIdForPDA = (credentialId: string): Uint8Array => {
-  const credentialBuffer = Buffer.from(credentialId);
-
-  // Seed tối đa cho PDA là 32 bytes, trừ đi "multisig" (8 bytes) còn 24 bytes
-  let seedBuffer: Uint8Array;
-
-  if (credentialBuffer.length > 24) {
-    console.log(
-      "Credential ID dài quá 24 bytes, thực hiện hash để đảm bảo đồng nhất với smart contract",
-    );
-
-    // Dùng cách XOR hash giống như trong smart contract
-    const hashResult = new Uint8Array(24);
-    for (let i = 0; i < credentialBuffer.length; i++) {
-      hashResult[i % 24] ^= credentialBuffer[i];
-    }
-
-    seedBuffer = hashResult;
-  } else {
-    // Nếu không quá dài, tạo buffer mới với độ dài cố định 24 bytes, padding với 0
-    seedBuffer = new Uint8Array(24);
-    seedBuffer.set(credentialBuffer);
-  }
-
-  return seedBuffer;
+  return sha256(Buffer.from(credentialId));
 };
 
 /**
@@ -41,7 +25,23 @@ export const getMultisigPDA = (credentialId: string): PublicKey => {
   const seedBuffer = processCredentialIdForPDA(credentialId);
 
   const [pda] = PublicKey.findProgramAddressSync(
-    [Buffer.from("multisig"), seedBuffer],
+    [
+      Buffer.from("multisig"),
+      Buffer.from([MULTISIG_SEED_VERSION]),
+      seedBuffer,
+    ],
+    PROGRAM_ID,
+  );
+
+  return pda;
+};
+
+/**
+ * Lấy guardian registry PDA của một ví multisig
+ */
+export const getGuardianRegistryPDA = (multisigPDA: PublicKey): PublicKey => {
+  const [pda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("guardian_registry"), multisigPDA.toBuffer()],
     PROGRAM_ID,
   );
 
@@ -66,3 +66,35 @@ export const getGuardianPDA = (
 
   return pda;
 };
+
+/**
+ * Lấy PDA của yêu cầu khôi phục ứng với recovery_nonce hiện tại của ví
+ */
+export const getRecoveryRequestPDA = (
+  multisigPDA: PublicKey,
+  recoveryNonce: number | bigint,
+): PublicKey => {
+  const nonceBytes = new Uint8Array(8);
+  const view = new DataView(nonceBytes.buffer);
+  view.setBigUint64(0, BigInt(recoveryNonce), true);
+
+  const [pda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("recovery"), multisigPDA.toBuffer(), nonceBytes],
+    PROGRAM_ID,
+  );
+
+  return pda;
+};
+
+/**
+ * Lấy vault PDA của ví: tài khoản thuộc System Program mà ví ký thay trong hành động Invoke,
+ * dùng làm nguồn cho các lệnh chuyển SOL qua System Program
+ */
+export const getVaultPDA = (multisigPDA: PublicKey): PublicKey => {
+  const [pda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("vault"), multisigPDA.toBuffer()],
+    PROGRAM_ID,
+  );
+
+  return pda;
+};
@@ -2,14 +2,11 @@ import {
   PublicKey,
   Keypair,
   Transaction,
-  TransactionInstruction,
   LAMPORTS_PER_SOL,
-  SYSVAR_CLOCK_PUBKEY,
-  SystemProgram,
 } from "@solana/web3.js";
 import { getMultisigPDA } from "@/utils/credentialUtils";
 import { getWalletByCredentialId } from "@/lib/firebase/webAuthnService";
-import { PROGRAM_ID, connection, MoonWalletProgram } from "@/lib/solana/index";
+import { PROGRAM_ID, connection, program, MoonWalletProgram } from "@/lib/solana/index";
 import { BN } from "@coral-xyz/anchor";
 
 /**
@@ -272,7 +269,8 @@ export const loadProposals = async (
 };
 
 /**
- * Tạo đề xuất giao dịch mới
+ * Tạo đề xuất giao dịch mới.
+ * payerKeypair đồng thời là guardian_signer, nên chỉ dùng được cho guardian xác thực bằng keypair
  */
 export const createProposal = async (
   multisigAddress: PublicKey,
@@ -308,8 +306,16 @@ export const createProposal = async (
       PROGRAM_ID
     );
 
-    // Tạo proposal ID dựa trên timestamp
-    const proposalId = new BN(Date.now());
+    // Proposal ID do chương trình cấp theo next_proposal_id của ví
+    const multisigAccount = await connection.getAccountInfo(multisigPubkey);
+    if (!multisigAccount) {
+      throw new Error("Không tìm thấy ví multisig");
+    }
+    const multisigData = program.coder.accounts.decode(
+      "multiSigWallet",
+      multisigAccount.data
+    );
+    const proposalId = new BN(multisigData.nextProposalId.toString());
 
     // Tính toán địa chỉ PDA cho proposal
     const [proposalPubkey] = findProgramAddress(
@@ -330,58 +336,24 @@ export const createProposal = async (
     // Sử dụng transaction có sẵn hoặc tạo mới
     const tx = existingTransaction || new Transaction();
 
-    // Discriminator cho createProposal
-    const createProposalDiscriminator = new Uint8Array([
-      132, 116, 68, 174, 216, 160, 198, 22,
-    ]);
-
-    // Tạo dữ liệu instruction
-    const descriptionBuffer = Buffer.from(params.description);
-    const descriptionLenBuffer = Buffer.alloc(4);
-    descriptionLenBuffer.writeUInt32LE(descriptionBuffer.length, 0);
-
-    const actionBuffer = Buffer.from("transfer");
-    const actionLenBuffer = Buffer.alloc(4);
-    actionLenBuffer.writeUInt32LE(actionBuffer.length, 0);
-
-    // Tạo data instruction
-    const data = Buffer.concat([
-      Buffer.from(createProposalDiscriminator),
-      Buffer.from(proposalId.toArrayLike(Buffer, "le", 8)),
-      Buffer.from(descriptionLenBuffer),
-      descriptionBuffer,
-      Buffer.from(guardianId.toArrayLike(Buffer, "le", 8)),
-      Buffer.from(actionLenBuffer),
-      actionBuffer,
-      // ActionParams với định dạng đúng
-      // 1. amount (option<u64>): Some variant (1) + u64 value
-      Buffer.from([1]), // Some variant cho amount
-      Buffer.from(amountLamports.toArrayLike(Buffer, "le", 8)),
-      // 2. destination (option<publicKey>): Some variant (1) + public key (32 bytes)
-      Buffer.from([1]), // Some variant cho destination
-      destinationPubkey.toBuffer(),
-      // 3. tokenMint (option<publicKey>): None variant (0)
-      Buffer.from([0]), // None variant cho tokenMint
-    ]);
-
-    // Thêm instruction vào transaction
+    // Thêm instruction vào transaction, dữ liệu được encode theo IDL
     tx.add(
-      new TransactionInstruction({
-        keys: [
-          { pubkey: multisigPubkey, isSigner: false, isWritable: true },
-          { pubkey: proposalPubkey, isSigner: false, isWritable: true },
-          { pubkey: guardianPubkey, isSigner: false, isWritable: false },
-          { pubkey: payerKeypair.publicKey, isSigner: true, isWritable: true },
-          { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
-          {
-            pubkey: SystemProgram.programId,
-            isSigner: false,
-            isWritable: false,
-          },
-        ],
-        programId: PROGRAM_ID,
-        data,
-      })
+      await program.methods
+        .createProposal(
+          params.description,
+          guardianId,
+          [{ transferSol: { amount: amountLamports, destination: destinationPubkey } }],
+          null, // expires_at: dùng TTL mặc định của ví
+          null // assertion: xác thực qua guardian_signer
+        )
+        .accountsPartial({
+          multisig: multisigPubkey,
+          proposal: proposalPubkey,
+          proposerGuardian: guardianPubkey,
+          guardianSigner: payerKeypair.publicKey,
+          payer: payerKeypair.publicKey,
+        })
+        .instruction()
     );
 
     // Gửi transaction
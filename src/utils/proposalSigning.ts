@@ -1,14 +1,15 @@
-import { PublicKey, Transaction, TransactionInstruction, Connection, SystemProgram,Keypair } from '@solana/web3.js';
+import { PublicKey, Transaction, TransactionInstruction, Connection, Keypair } from '@solana/web3.js';
 import { BN } from '@coral-xyz/anchor';
 import { sha256 } from '@noble/hashes/sha256';
 import { getWalletByCredentialId } from '../lib/firebase/webAuthnService';
 import { getGuardianPDA } from './credentialUtils';
 import { addSignerToProposal, Proposal} from '../lib/firebase/proposalService';
-import { PROGRAM_ID } from './constants';
+import { PROGRAM_ID, CLUSTER_ID } from './constants';
 import { compressPublicKey } from './bufferUtils';
 import { collection, query, where, getDocs } from 'firebase/firestore';
 import { db } from '../lib/firebase/config';
 import { createSecp256r1Instruction } from '../lib/solana/secp256r1';
+import { program } from '../lib/solana';
 
 
 
@@ -16,8 +17,56 @@ import { createSecp256r1Instruction } from '../lib/solana/secp256r1';
 const SECP256R1_ORDER = new BN('FFFFFFFF00000000FFFFFFFFFFFFFFFFBCE6FAADA7179E84F3B9CAC2FC632551', 16);
 const SECP256R1_HALF_ORDER = SECP256R1_ORDER.shrn(1);
 
-// Các hằng số khác
-// Các hằng số này cũng có thể import nhưng được định nghĩa ở đây để dễ kiểm soát
+// Phải khớp với SIGNING_PAYLOAD_VERSION trong chương trình (state/guardian.rs)
+const SIGNING_PAYLOAD_VERSION = 2;
+
+/**
+ * Tạo SigningPayload (Borsh) giống chương trình. Challenge WebAuthn là sha256 của payload này
+ */
+export const buildSigningPayload = (params: {
+  multisig: PublicKey;
+  target: PublicKey;
+  action: string;
+  guardianId: number;
+  nonce: BN;
+}): Buffer => {
+  return Buffer.concat([
+    Buffer.from([SIGNING_PAYLOAD_VERSION, CLUSTER_ID]),
+    PROGRAM_ID.toBuffer(),
+    params.multisig.toBuffer(),
+    params.target.toBuffer(),
+    Buffer.from(sha256(Buffer.from(params.action))),
+    new BN(params.guardianId).toArrayLike(Buffer, "le", 8),
+    params.nonce.toArrayLike(Buffer, "le", 8),
+  ]);
+};
+
+const toHex = (bytes: Uint8Array | number[]): string => Buffer.from(bytes).toString('hex');
+
+/**
+ * Đọc nonce hiện tại của guardian và contents_hash của đề xuất từ blockchain
+ */
+async function fetchApprovalState(
+  connection: Connection,
+  guardianPDA: PublicKey,
+  proposalPDA: PublicKey
+): Promise<{ nonce: BN; contentsHash: number[] }> {
+  const [guardianAccount, proposalAccount] = await connection.getMultipleAccountsInfo([
+    guardianPDA,
+    proposalPDA
+  ]);
+  if (!guardianAccount) {
+    throw new Error("Không tìm thấy tài khoản guardian");
+  }
+  if (!proposalAccount) {
+    throw new Error("Không tìm thấy đề xuất");
+  }
+
+  const guardianData = program.coder.accounts.decode("guardian", guardianAccount.data);
+  const proposalData = program.coder.accounts.decode("transactionProposal", proposalAccount.data);
+
+  return { nonce: guardianData.nonce, contentsHash: proposalData.contentsHash };
+}
 
 /**
  * Hàm chính để ký đề xuất
@@ -40,26 +89,51 @@ export const handleSignProposal = async (
   }
   
   try {
-    // Tạo thông điệp để ký
-    const timestamp = Math.floor(Date.now() / 1000);
     const proposalId = currentSigningProposal.proposalId;
     
     console.log("Đang chuẩn bị thông tin để ký đề xuất ID:", proposalId);
     
-    // Step 1: Yêu cầu người dùng xác thực với WebAuthn
-    const initialChallenge = new Uint8Array(32);
-    window.crypto.getRandomValues(initialChallenge);
+    // Tính PDA cho guardian và proposal
+    const multisigPDA = walletAddressPubkey;
+    const guardianPDA = getGuardianPDA(multisigPDA, guardianId);
+    const [proposalPubkey] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("proposal"),
+        multisigPDA.toBuffer(),
+        new BN(proposalId).toArrayLike(Buffer, "le", 8)
+      ],
+      PROGRAM_ID
+    );
     
-    // Yêu cầu người dùng chọn khóa WebAuthn
-    console.log("Yêu cầu người dùng chọn khóa WebAuthn để xác thực");
+    // Step 1: Đọc nonce của guardian và nội dung đề xuất đang được phê duyệt
+    const { nonce, contentsHash } = await fetchApprovalState(connection, guardianPDA, proposalPubkey);
+    
+    // Step 2: Tạo challenge từ SigningPayload mà chương trình sẽ dựng lại khi xác thực
+    const action = `approve:proposal_${proposalId},contents_${toHex(contentsHash)}`;
+    const payload = buildSigningPayload({
+      multisig: multisigPDA,
+      target: proposalPubkey,
+      action,
+      guardianId,
+      nonce
+    });
+    console.log("Hành động được ký:", action, "nonce:", nonce.toString());
+    
+    // Step 3: Yêu cầu người dùng ký challenge bằng passkey
     let credential;
     try {
       credential = await navigator.credentials.get({
         publicKey: {
-          challenge: initialChallenge,
+          challenge: sha256(payload),
           timeout: 60000,
           userVerification: 'required',
           rpId: window.location.hostname,
+          allowCredentials: credentialId
+            ? [{
+                id: Uint8Array.from(atob(credentialId), (c) => c.charCodeAt(0)),
+                type: 'public-key' as PublicKeyCredentialType,
+              }]
+            : undefined,
         }
       }) as PublicKeyCredential;
     } catch (error) {
@@ -71,97 +145,34 @@ export const handleSignProposal = async (
     const credentialIdString = Buffer.from(credential.rawId).toString('base64');
     console.log("Người dùng đã chọn credential ID:", credentialIdString);
     
-    // Step 2: Lấy thông tin WebAuthn public key từ credential 
+    // Step 4: Lấy thông tin WebAuthn public key từ credential 
     const credentialMapping = await getWalletByCredentialId(credentialIdString);
     
     if (!credentialMapping?.guardianPublicKey) {
       throw new Error("Không tìm thấy thông tin public key cho credential này");
     }
     
-    // Lấy WebAuthn public key
     const webAuthnPubKey = Buffer.from(credentialMapping.guardianPublicKey);
-    
-    // In thông tin chi tiết về public key để debug
-    console.log("=========== PUBLIC KEY DEBUG INFO ===========");
-    console.log("WebAuthn Public Key length:", webAuthnPubKey.length);
-    
-    let formatDescription;
-    if (webAuthnPubKey[0] === 0x04) {
-      formatDescription = 'Uncompressed (0x04)';
-    } else if (webAuthnPubKey[0] === 0x02 || webAuthnPubKey[0] === 0x03) {
-      formatDescription = `Compressed (0x${webAuthnPubKey[0].toString(16)})`;
-    } else {
-      const hexValue = webAuthnPubKey[0]?.toString(16) ?? 'undefined';
-      formatDescription = `Unknown (0x${hexValue})`;
-    }
-    console.log("WebAuthn Public Key format:", formatDescription);
-    console.log("WebAuthn Public Key hex:", webAuthnPubKey.toString('hex'));
-    // Kiểm tra định dạng JSON để xem có thể parse được không
-    try {
-      const decoded = JSON.parse(webAuthnPubKey.toString());
-      console.log("WebAuthn Public Key là JSON:", decoded);
-    } catch (e) {
-      console.log("WebAuthn Public Key không phải JSON (binary data)");
-      console.warn("Chi tiết lỗi parse:", e);
-    }
-    console.log("=========================================");
-    
-    // Step 3: Tính hash của public key (6 bytes đầu tiên)
-    const hashBytes = sha256(webAuthnPubKey);
-    const pubkeyHashBytes = hashBytes.slice(0, 6);
-    const pubkeyHashHex = Array.from(pubkeyHashBytes)
-      .map(b => b.toString(16).padStart(2, '0'))
-      .join('');
-    
-    // Step 4: Tạo thông điệp đầy đủ để ký
-    const messageWithPubkey = `approve:proposal_${proposalId},guardian_${guardianId},timestamp:${timestamp},pubkey:${pubkeyHashHex}`;
-    console.log("Thông điệp đầy đủ để ký:", messageWithPubkey);
+    logPublicKeyDebugInfo(webAuthnPubKey);
     
     // Lấy response từ credential
     const response = credential.response as AuthenticatorAssertionResponse;
     
-    // Chuẩn bị dữ liệu để ký
-    const signature = new Uint8Array(response.signature);
-    const authenticatorData = new Uint8Array(response.authenticatorData);
-    const clientDataJSON = new Uint8Array(response.clientDataJSON);
-    
     console.log('Đã ký thành công bằng WebAuthn');
     
-    // Step 5: Tiếp tục quy trình gửi transaction lên blockchain
-    // Tính PDA cho multisig và guardian
-    const multisigPDA = walletAddressPubkey;
-    const guardianPDA = getGuardianPDA(multisigPDA, guardianId);
-    
-    // Tạo proposalPDA từ multisigPDA và proposalId
-    const [proposalPubkey] = PublicKey.findProgramAddressSync(
-      [
-        Buffer.from("proposal"),
-        multisigPDA.toBuffer(),
-        new BN(proposalId).toArrayLike(Buffer, "le", 8)
-      ],
-      PROGRAM_ID
-    );
-    
-    console.log("Tạo giao dịch với thông tin:");
-    console.log("- MultisigPDA:", multisigPDA.toString());
-    console.log("- GuardianPDA:", guardianPDA.toString());
-    console.log("- ProposalPDA:", proposalPubkey.toString());
-    console.log("- GuardianID:", guardianId);
-    console.log("- Timestamp:", timestamp);
-    
-    // Tạo transaction để approve proposal
+    // Step 5: Tạo transaction approve_proposal kèm instruction secp256r1
     const tx = await createApproveProposalTx({
       proposalPubkey,
       multisigPDA,
       guardianPDA,
       guardianId,
       feePayer: projectFeePayerKeypair.publicKey,
-      webauthnSignature: signature,
-      authenticatorData,
-      clientDataJSON,
+      webauthnSignature: new Uint8Array(response.signature),
+      authenticatorData: new Uint8Array(response.authenticatorData),
+      clientDataJSON: new Uint8Array(response.clientDataJSON),
       proposalId,
-      timestamp,
-      credentialId: credentialIdString
+      contentsHash,
+      webAuthnPubKey
     });
     
     // Thiết lập recent blockhash và fee payer
@@ -187,14 +198,6 @@ export const handleSignProposal = async (
       webAuthnPubKey.toString('hex')
     );
     
-    // Kiểm tra ngưỡng chữ ký (threshold) và cập nhật trạng thái nếu đạt đủ
-    console.log("currentSigningProposal:", JSON.stringify({
-      proposalId: currentSigningProposal.proposalId,
-      threshold: currentSigningProposal.threshold,
-      signers: currentSigningProposal.signers ?? [],
-      status: currentSigningProposal.status
-    }, null, 2));
-    
     return txSignature;
   } catch (error) {
     console.error("Lỗi khi ký đề xuất:", error);
@@ -213,11 +216,10 @@ interface ApproveProposalParams {
   authenticatorData: Uint8Array;
   clientDataJSON: Uint8Array;
   proposalId: number;
-  timestamp: number;
-  credentialId?: string;
+  contentsHash: number[];
+  webAuthnPubKey: Buffer;
 }
 
-// Vấn đề S107: Quá nhiều tham số
 export const createApproveProposalTx = async (
   params: ApproveProposalParams
 ): Promise<Transaction> => {
@@ -231,156 +233,73 @@ export const createApproveProposalTx = async (
     authenticatorData,
     clientDataJSON,
     proposalId,
-    timestamp,
-    credentialId
+    contentsHash,
+    webAuthnPubKey
   } = params;
 
-  // Tạo transaction mới
   const transaction = new Transaction();
 
-  // Lấy WebAuthn public key từ credential, truyền credentialId nếu có
-  console.log("Đang tìm WebAuthn public key...");
-  
-  try {
-    // Lấy thông tin credential mapping từ Firebase
-    const credentialMapping = await getWalletByCredentialId(credentialId ?? '');
-    if (!credentialMapping?.guardianPublicKey) {
-      throw new Error("Không tìm thấy WebAuthn public key cho guardian này");
-    }
-    
-    const webAuthnPubKey = Buffer.from(credentialMapping.guardianPublicKey);
-    
-    // In thông tin chi tiết về public key để debug
-    console.log("=========== PUBLIC KEY DEBUG INFO ===========");
-    console.log("WebAuthn Public Key length:", webAuthnPubKey.length);
-    
-    let formatDescription;
-    if (webAuthnPubKey[0] === 0x04) {
-      formatDescription = 'Uncompressed (0x04)';
-    } else if (webAuthnPubKey[0] === 0x02 || webAuthnPubKey[0] === 0x03) {
-      formatDescription = `Compressed (0x${webAuthnPubKey[0].toString(16)})`;
-    } else {
-      const hexValue = webAuthnPubKey[0]?.toString(16) ?? 'undefined';
-      formatDescription = `Unknown (0x${hexValue})`;
-    }
-    console.log("WebAuthn Public Key format:", formatDescription);
-    console.log("WebAuthn Public Key hex:", webAuthnPubKey.toString('hex'));
-    // Kiểm tra định dạng JSON để xem có thể parse được không
-    try {
-      const decoded = JSON.parse(webAuthnPubKey.toString());
-      console.log("WebAuthn Public Key là JSON:", decoded);
-    } catch (e) {
-      console.log("WebAuthn Public Key không phải JSON (binary data)");
-      console.warn("Chi tiết lỗi parse:", e);
-    }
-    console.log("=========================================");
-    
-    // Tính hash của WebAuthn public key
-    const hashBytes = sha256(webAuthnPubKey);
-    // Lấy 6 bytes đầu tiên
-    const pubkeyHashBytes = hashBytes.slice(0, 6);
-    // Chuyển đổi sang hex string
-    const pubkeyHashHex = Array.from(pubkeyHashBytes)
-      .map(b => b.toString(16).padStart(2, '0'))
-      .join('');
+  // 1. Passkey ký authenticatorData || sha256(clientDataJSON)
+  const verificationData = await createWebAuthnVerificationData({
+    signature: webauthnSignature,
+    authenticatorData,
+    clientDataJSON
+  });
 
-    // 1. Tạo message để ký
-    const messageString = `approve:proposal_${proposalId},guardian_${guardianId},timestamp:${timestamp},pubkey:${pubkeyHashHex}`;
-    const messageBuffer = Buffer.from(messageString);
-    console.log("Thông điệp được ký:", messageString);
+  // 2. Chuyển chữ ký DER sang raw và chuẩn hóa về dạng Low-S
+  const normalizedSignature = normalizeSignatureToLowS(Buffer.from(derToRaw(webauthnSignature)));
 
-    // 2. Tính hash của clientDataJSON
-    const clientDataHash = await crypto.subtle.digest('SHA-256', clientDataJSON);
-    const clientDataHashBytes = new Uint8Array(clientDataHash);
+  // 3. Instruction secp256r1 để chương trình tìm thấy chữ ký trong transaction
+  const secp256r1Ix = createSecp256r1Instruction(
+    Buffer.from(verificationData),
+    compressPublicKey(webAuthnPubKey),
+    normalizedSignature,
+    false
+  );
+  transaction.add(secp256r1Ix);
 
-    // 3. Tạo verification data: authenticatorData + hash(clientDataJSON)
-    const verificationData = new Uint8Array(authenticatorData.length + clientDataHashBytes.length);
-    verificationData.set(new Uint8Array(authenticatorData), 0);
-    verificationData.set(clientDataHashBytes, authenticatorData.length);
+  // 4. Instruction approve_proposal, dữ liệu được encode theo IDL
+  const [signaturePDA] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("signature"),
+      proposalPubkey.toBuffer(),
+      new BN(guardianId).toArrayLike(Buffer, "le", 8)
+    ],
+    PROGRAM_ID
+  );
 
-    // 4. Chuyển đổi signature từ DER sang raw format
-    const rawSignature = derToRaw(webauthnSignature);
-    
-    // 5. Chuẩn hóa signature về dạng Low-S
-    const normalizedSignature = normalizeSignatureToLowS(Buffer.from(rawSignature));
-    
-    // Chuyển đổi khóa công khai thành dạng nén (compressed)
-    const compressedPubKey = compressPublicKey(webAuthnPubKey);
-    
-    // 6. Tạo instruction Secp256r1 để xác thực chữ ký
-    const secp256r1Ix = createSecp256r1Instruction(
-      Buffer.from(verificationData),
-      compressedPubKey,
-      normalizedSignature,
-      false
-    );
-    
-    // Thêm secp256r1 instruction vào transaction
-    transaction.add(secp256r1Ix);
-    
-    // 7. Tạo instruction approve_proposal
-    
-    // Tạo dữ liệu cho approve_proposal instruction
-    const approveProposalDiscriminator = Buffer.from([136, 108, 102, 85, 98, 114, 7, 147]); // Discriminator từ IDL
-    
-    // Tạo các buffer cho tham số
-    const proposalIdBuffer = Buffer.alloc(8);
-    proposalIdBuffer.writeBigUInt64LE(BigInt(proposalId), 0);
-    
-    const guardianIdBuffer = Buffer.alloc(8);
-    guardianIdBuffer.writeBigUInt64LE(BigInt(guardianId), 0);
-    
-    const timestampBuffer = Buffer.alloc(8);
-    timestampBuffer.writeBigInt64LE(BigInt(timestamp), 0);
-    
-    // Tạo message buffer và độ dài
-    const messageLenBuffer = Buffer.alloc(4);
-    messageLenBuffer.writeUInt32LE(messageBuffer.length, 0);
-    
-    // Tạo dữ liệu instruction
-    const approveData = Buffer.concat([
-      approveProposalDiscriminator,
-      proposalIdBuffer,
-      guardianIdBuffer,
-      timestampBuffer,
-      messageLenBuffer,
-      messageBuffer
-    ]);
-    
-    // Tìm PDA cho signature từ proposal và guardianId
-    const [signaturePDA] = PublicKey.findProgramAddressSync(
-      [
-        Buffer.from("signature"),
-        proposalPubkey.toBuffer(),
-        new BN(guardianId).toArrayLike(Buffer, "le", 8)
-      ],
-      PROGRAM_ID
-    );
-    
-    // Tạo instruction approve_proposal với các tài khoản theo đúng thứ tự của IDL
-    const approveInstruction = new TransactionInstruction({
-      keys: [
-        { pubkey: multisigPDA, isSigner: false, isWritable: true },
-        { pubkey: proposalPubkey, isSigner: false, isWritable: true },
-        { pubkey: signaturePDA, isSigner: false, isWritable: true },
-        { pubkey: guardianPDA, isSigner: false, isWritable: false },
-        { pubkey: feePayer, isSigner: true, isWritable: true },
-        { pubkey: new PublicKey('Sysvar1nstructions1111111111111111111111111'), isSigner: false, isWritable: false },
-        { pubkey: new PublicKey('SysvarC1ock11111111111111111111111111111111'), isSigner: false, isWritable: false },
-        { pubkey: new PublicKey('11111111111111111111111111111111'), isSigner: false, isWritable: false }
-      ],
-      programId: PROGRAM_ID,
-      data: approveData
-    });
-    
-    // Thêm instruction vào transaction
-    transaction.add(approveInstruction);
-    
-    return transaction;
-  } catch (error) {
-    console.error("Lỗi khi tạo transaction approve proposal:", error);
-    throw error;
-  }
+  const approveInstruction: TransactionInstruction = await program.methods
+    .approveProposal(
+      new BN(proposalId),
+      new BN(guardianId),
+      contentsHash,
+      {
+        authenticatorData: Buffer.from(authenticatorData),
+        clientDataJson: Buffer.from(clientDataJSON),
+      }
+    )
+    .accountsPartial({
+      multisig: multisigPDA,
+      proposal: proposalPubkey,
+      signature: signaturePDA,
+      // Chương trình từ chối phê duyệt nếu guardian đã từ chối đề xuất này
+      existingRejection: PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("rejection"),
+          proposalPubkey.toBuffer(),
+          new BN(guardianId).toArrayLike(Buffer, "le", 8)
+        ],
+        PROGRAM_ID
+      )[0],
+      guardian: guardianPDA,
+      guardianSigner: null,
+      payer: feePayer,
+    })
+    .instruction();
+
+  transaction.add(approveInstruction);
+
+  return transaction;
 };
 
 /**
@@ -753,23 +672,6 @@ async function getGuardianCredentialByMultisigAndId(multisigAddress: string, gua
   }
 }
 
-/**
- * Cập nhật danh sách người ký đề xuất trong Firebase
- */
-async function updateProposalSigners(proposal: Proposal, guardianId: number, txSignature: string) {
-  try {
-    // Sử dụng hàm addSignerToProposal
-    await addSignerToProposal(
-      proposal.multisigAddress,
-      proposal.proposalId,
-      `guardian_${guardianId}`
-    );
-    console.log(`Đã cập nhật thông tin người ký cho đề xuất ${proposal.proposalId}, guardian ${guardianId}`);
-  } catch (error) {
-    console.error("Lỗi khi cập nhật thông tin người ký:", error);
-  }
-}
-
 /**
  * Lấy guardianId từ tham số hoặc localStorage
  */
@@ -839,178 +741,6 @@ function checkLocalStorageGuardianInfo(storedCredentialId: string, actualGuardia
   }
 }
 
-/**
- * Tính toán PDA cho guardian và proposal
- */
-function calculatePDAs(
-  multisigPDA: PublicKey,
-  guardianId: number,
-  proposalId: number
-): { guardianPDA: PublicKey; proposalPDA: PublicKey } {
-  // Tính toán guardian PDA
-  const [guardianPDA] = PublicKey.findProgramAddressSync(
-    [
-      Buffer.from("guardian"),
-      multisigPDA.toBuffer(),
-      new BN(guardianId).toArrayLike(Buffer, "le", 8),
-    ],
-    PROGRAM_ID
-  );
-  
-  console.log("Guardian PDA:", guardianPDA.toString());
-  
-  // Tính toán proposal PDA
-  const [proposalPDA] = PublicKey.findProgramAddressSync(
-    [
-      Buffer.from("proposal"),
-      multisigPDA.toBuffer(),
-      new BN(proposalId).toArrayLike(Buffer, "le", 8),
-    ],
-    PROGRAM_ID
-  );
-  
-  console.log("Proposal PDA:", proposalPDA.toString());
-  
-  return { guardianPDA, proposalPDA };
-}
-
-/**
- * Lấy chữ ký WebAuthn cho đề xuất
- */
-async function getWebAuthnSignature(
-  proposalId: number,
-  credentialId: string
-): Promise<{ 
-  signature: Uint8Array; 
-  clientDataJSON: Uint8Array;
-  challenge: Buffer;
-  timestamp: number;
-}> {
-  // Tạo thông điệp cần ký
-  const timestamp = Math.floor(Date.now() / 1000);
-  console.log("Current timestamp:", timestamp);
-  
-  const challenge = Buffer.concat([
-    new BN(proposalId).toArrayLike(Buffer, "le", 8),
-    new BN(timestamp).toArrayLike(Buffer, "le", 8),
-  ]);
-  
-  // Tạo tùy chọn cho WebAuthn
-  const publicKeyCredentialRequestOptions = {
-    challenge,
-    timeout: 60000,
-    rpId: window.location.hostname,
-    userVerification: "preferred" as UserVerificationRequirement,
-    allowCredentials: [
-      {
-        id: Uint8Array.from(
-          atob(credentialId),
-          (c) => c.charCodeAt(0)
-        ),
-        type: "public-key" as PublicKeyCredentialType,
-      },
-    ],
-  };
-  
-  console.log("Đang yêu cầu WebAuthn signature...");
-  
-  // Yêu cầu chữ ký WebAuthn
-  const credential = (await navigator.credentials.get({
-    publicKey: publicKeyCredentialRequestOptions,
-  })) as PublicKeyCredential;
-  
-  // Xử lý kết quả và lấy chữ ký
-  const response = credential.response as AuthenticatorAssertionResponse;
-  const signature = new Uint8Array(response.signature);
-  const clientDataJSON = new Uint8Array(response.clientDataJSON);
-  
-  console.log("Đã nhận WebAuthn signature");
-  
-  return { 
-    signature, 
-    clientDataJSON,
-    challenge,
-    timestamp
-  };
-}
-
-/**
- * Tạo instruction approve_proposal
- */
-function createApproveProposalInstruction(
-  params: {
-    multisigPDA: PublicKey;
-    guardianPDA: PublicKey;
-    proposalPDA: PublicKey;
-    feePayer: PublicKey;
-    proposalId: number;
-    timestamp: number;
-    clientDataJSON: Uint8Array;
-    signature: Uint8Array;
-    guardianWebAuthnPubkey: Uint8Array;
-  }
-): TransactionInstruction {
-  const {
-    multisigPDA,
-    guardianPDA,
-    proposalPDA,
-    feePayer,
-    proposalId,
-    timestamp,
-    clientDataJSON,
-    signature,
-    guardianWebAuthnPubkey
-  } = params;
-  
-  // Tạo discriminator từ IDL
-  const approveProposalDiscriminator = Buffer.from([136, 108, 102, 85, 98, 114, 7, 147]);
-
-  // Tạo các buffer cho tham số
-  const proposalIdBuffer = Buffer.alloc(8);
-  proposalIdBuffer.writeBigUInt64LE(BigInt(proposalId), 0);
-
-  const timestampBuffer = Buffer.alloc(8);
-  timestampBuffer.writeBigInt64LE(BigInt(timestamp), 0);
-
-  // Chuẩn bị clientDataJSON buffer và độ dài
-  const clientDataJSONLenBuffer = Buffer.alloc(4);
-  clientDataJSONLenBuffer.writeUInt32LE(clientDataJSON.length, 0);
-
-  // Chuẩn bị signature buffer và độ dài
-  const signatureLenBuffer = Buffer.alloc(4);
-  signatureLenBuffer.writeUInt32LE(signature.length, 0);
-
-  // Chuẩn bị guardianPublicKey buffer và độ dài
-  const guardianPubkeyLenBuffer = Buffer.alloc(4);
-  guardianPubkeyLenBuffer.writeUInt32LE(guardianWebAuthnPubkey.length, 0);
-
-  // Tạo dữ liệu instruction
-  const approveData = Buffer.concat([
-    approveProposalDiscriminator,
-    proposalIdBuffer,
-    timestampBuffer,
-    clientDataJSONLenBuffer,
-    Buffer.from(clientDataJSON),
-    signatureLenBuffer,
-    Buffer.from(signature),
-    guardianPubkeyLenBuffer,
-    Buffer.from(guardianWebAuthnPubkey)
-  ]);
-
-  // Tạo instruction
-  return new TransactionInstruction({
-    keys: [
-      { pubkey: multisigPDA, isSigner: false, isWritable: false },
-      { pubkey: guardianPDA, isSigner: false, isWritable: false },
-      { pubkey: proposalPDA, isSigner: false, isWritable: true },
-      { pubkey: feePayer, isSigner: true, isWritable: true },
-      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
-    ],
-    programId: PROGRAM_ID,
-    data: approveData
-  });
-}
-
 /**
  * Ký đề xuất bằng WebAuthn và gửi transaction lên blockchain
  */
@@ -1025,82 +755,27 @@ export async function signProposalWithWebAuthn(
   console.log("Bắt đầu ký đề xuất với WebAuthn...");
   console.log("Proposal ID:", proposal.proposalId);
   
-  try {
-    // 1. Lấy guardianId từ tham số hoặc localStorage
-    const actualGuardianId = getActualGuardianId(guardianId);
-    console.log("Guardian ID cuối cùng sử dụng:", actualGuardianId);
-    
-    // 2. Lấy credentialId từ tham số hoặc localStorage
-    const actualCredentialId = getActualCredentialId(credentialId);
-    console.log("Đang sử dụng credentialId:", actualCredentialId);
-    
-    // 3. Kiểm tra thông tin mapping từ localStorage
-    const storedCredentialId = localStorage.getItem("current_credential_id");
-    checkLocalStorageGuardianInfo(storedCredentialId ?? "", actualGuardianId);
-    
-    // 4. Lấy WebAuthn public key của guardian từ Firebase
-    const guardianWebAuthnPubkey = await getGuardianWebAuthnPublicKey(multisigPDA, actualGuardianId);
-    
-    if (!guardianWebAuthnPubkey) {
-      throw new Error(`Không tìm thấy WebAuthn public key cho guardian ID ${actualGuardianId}`);
-    }
-    
-    // 5. Tính toán PDA cho guardian và proposal
-    const { guardianPDA, proposalPDA } = calculatePDAs(
-      multisigPDA, 
-      actualGuardianId, 
-      proposal.proposalId
-    );
-    
-    // 6. Lấy chữ ký WebAuthn cho đề xuất
-    const { 
-      signature, 
-      clientDataJSON, 
-      timestamp 
-    } = await getWebAuthnSignature(proposal.proposalId, actualCredentialId);
-    
-    // 7. Tạo instruction approve_proposal
-    const approveInstruction = createApproveProposalInstruction({
-      multisigPDA,
-      guardianPDA,
-      proposalPDA,
-      feePayer: feePayer.publicKey,
-      proposalId: proposal.proposalId,
-      timestamp,
-      clientDataJSON,
-      signature,
-      guardianWebAuthnPubkey
-    });
-    
-    // 8. Tạo và ký transaction
-    const transaction = new Transaction();
-    transaction.add(approveInstruction);
-    
-    // Lấy recent blockhash và thiết lập fee payer
-    transaction.recentBlockhash = (await connection.getLatestBlockhash('confirmed')).blockhash;
-    transaction.feePayer = feePayer.publicKey;
-    
-    // Ký transaction
-    transaction.sign(feePayer);
-    
-    // 9. Gửi transaction lên blockchain
-    const serializedTx = transaction.serialize();
-    const txSignature = await connection.sendRawTransaction(serializedTx, {
-      skipPreflight: false,
-      maxRetries: 3,
-      preflightCommitment: 'confirmed'
-    });
-    
-    console.log("Đã gửi transaction ký đề xuất:", txSignature);
-    
-    // 10. Cập nhật danh sách người ký trong Firebase
-    await updateProposalSigners(proposal, actualGuardianId, txSignature);
-    
-    return txSignature;
-  } catch (error) {
-    console.error("Lỗi trong quá trình ký đề xuất:", error);
-    throw error;
-  }
+  // 1. Lấy guardianId từ tham số hoặc localStorage
+  const actualGuardianId = getActualGuardianId(guardianId);
+  console.log("Guardian ID cuối cùng sử dụng:", actualGuardianId);
+  
+  // 2. Lấy credentialId từ tham số hoặc localStorage
+  const actualCredentialId = getActualCredentialId(credentialId);
+  console.log("Đang sử dụng credentialId:", actualCredentialId);
+  
+  // 3. Kiểm tra thông tin mapping từ localStorage
+  const storedCredentialId = localStorage.getItem("current_credential_id");
+  checkLocalStorageGuardianInfo(storedCredentialId ?? "", actualGuardianId);
+  
+  // 4. Ký và gửi transaction approve_proposal
+  return handleSignProposal(
+    connection,
+    proposal,
+    multisigPDA,
+    actualGuardianId,
+    feePayer,
+    actualCredentialId
+  );
 }
 
 /**
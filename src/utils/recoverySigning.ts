@@ -0,0 +1,287 @@
+import { PublicKey, Transaction, TransactionInstruction, Connection, Keypair } from '@solana/web3.js';
+import { BN } from '@coral-xyz/anchor';
+import { sha256 } from '@noble/hashes/sha256';
+import { getGuardianPDA, getGuardianRegistryPDA, getRecoveryRequestPDA } from './credentialUtils';
+import { compressPublicKey } from './bufferUtils';
+import {
+  buildSigningPayload,
+  createWebAuthnVerificationData,
+  derToRaw,
+  normalizeSignatureToLowS,
+} from './proposalSigning';
+import { getWalletByCredentialId } from '../lib/firebase/webAuthnService';
+import { createSecp256r1Instruction } from '../lib/solana/secp256r1';
+import { program } from '../lib/solana';
+
+const toHex = (bytes: Uint8Array | number[]): string => Buffer.from(bytes).toString('hex');
+
+/**
+ * Yêu cầu passkey của guardian ký challenge, trả về assertion và instruction secp256r1
+ * mà chương trình sẽ tìm trong transaction
+ */
+async function signWithGuardianPasskey(
+  challenge: Uint8Array,
+  credentialId?: string
+): Promise<{
+  secp256r1Instruction: TransactionInstruction;
+  assertion: { authenticatorData: Buffer; clientDataJson: Buffer };
+}> {
+  let credential;
+  try {
+    credential = await navigator.credentials.get({
+      publicKey: {
+        challenge,
+        timeout: 60000,
+        userVerification: 'required',
+        rpId: window.location.hostname,
+        allowCredentials: credentialId
+          ? [{
+              id: Uint8Array.from(atob(credentialId), (c) => c.charCodeAt(0)),
+              type: 'public-key' as PublicKeyCredentialType,
+            }]
+          : undefined,
+      }
+    }) as PublicKeyCredential;
+  } catch (error) {
+    console.error("Lỗi khi yêu cầu WebAuthn:", error);
+    throw new Error("Người dùng đã hủy xác thực hoặc xác thực thất bại");
+  }
+
+  const credentialMapping = await getWalletByCredentialId(
+    Buffer.from(credential.rawId).toString('base64')
+  );
+  if (!credentialMapping?.guardianPublicKey) {
+    throw new Error("Không tìm thấy thông tin public key cho credential này");
+  }
+  const response = credential.response as AuthenticatorAssertionResponse;
+  const authenticatorData = new Uint8Array(response.authenticatorData);
+  const clientDataJSON = new Uint8Array(response.clientDataJSON);
+  const webauthnSignature = new Uint8Array(response.signature);
+
+  const verificationData = await createWebAuthnVerificationData({
+    signature: webauthnSignature,
+    authenticatorData,
+    clientDataJSON
+  });
+
+  return {
+    secp256r1Instruction: createSecp256r1Instruction(
+      Buffer.from(verificationData),
+      compressPublicKey(Buffer.from(credentialMapping.guardianPublicKey)),
+      normalizeSignatureToLowS(Buffer.from(derToRaw(webauthnSignature))),
+      false
+    ),
+    assertion: {
+      authenticatorData: Buffer.from(authenticatorData),
+      clientDataJson: Buffer.from(clientDataJSON),
+    },
+  };
+}
+
+async function sendWithFeePayer(
+  connection: Connection,
+  transaction: Transaction,
+  feePayerKeypair: Keypair
+): Promise<string> {
+  transaction.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
+  transaction.feePayer = feePayerKeypair.publicKey;
+  transaction.partialSign(feePayerKeypair);
+
+  return await connection.sendRawTransaction(transaction.serialize(), {
+    skipPreflight: false,
+    preflightCommitment: 'confirmed',
+    maxRetries: 3
+  });
+}
+
+async function fetchRecoveryNonce(connection: Connection, multisigPDA: PublicKey): Promise<number> {
+  const multisigInfo = await connection.getAccountInfo(multisigPDA);
+  if (!multisigInfo) {
+    throw new Error("Không tìm thấy ví multisig");
+  }
+  const multisig = program.coder.accounts.decode("multiSigWallet", multisigInfo.data);
+  return Number(multisig.recoveryNonce);
+}
+
+/**
+ * Tìm owner có recovery_hash khớp với recovery phrase, đó là guardian bị thay thế
+ */
+async function findOwnerByRecoveryHash(
+  connection: Connection,
+  multisigPDA: PublicKey,
+  recoveryHashIntermediate: Uint8Array
+): Promise<number | null> {
+  const registryInfo = await connection.getAccountInfo(getGuardianRegistryPDA(multisigPDA));
+  if (!registryInfo) {
+    throw new Error("Không tìm thấy guardian registry của ví");
+  }
+  const registry = program.coder.accounts.decode("guardianRegistry", registryInfo.data);
+  const guardianIds: number[] = registry.guardianIds.map((id: any) => Number(id));
+
+  const guardianInfos = await connection.getMultipleAccountsInfo(
+    guardianIds.map((id) => getGuardianPDA(multisigPDA, id))
+  );
+  const recoveryHash = Buffer.from(sha256(recoveryHashIntermediate));
+
+  for (let i = 0; i < guardianIds.length; i++) {
+    const info = guardianInfos[i];
+    if (!info) continue;
+    const guardian = program.coder.accounts.decode("guardian", info.data);
+    if (
+      guardian.isOwner &&
+      guardian.isActive &&
+      Buffer.from(guardian.recoveryHash).equals(recoveryHash)
+    ) {
+      return guardianIds[i];
+    }
+  }
+  return null;
+}
+
+/**
+ * Guardian có quyền khôi phục tạo yêu cầu chuyển quyền owner sang chính mình với khoá WebAuthn mới.
+ * Owner bị thay thế được xác định qua recovery phrase; guardian ký bằng passkey hiện tại của mình
+ */
+export const handleInitiateRecovery = async (
+  connection: Connection,
+  multisigAddress: PublicKey | string,
+  guardianId: number,
+  recoveryHashIntermediate: Uint8Array,
+  newWebauthnPubkey: Uint8Array,
+  feePayerKeypair: Keypair,
+  credentialId?: string
+): Promise<{ signature: string; recoveryRequest: PublicKey }> => {
+  const multisigPDA = typeof multisigAddress === 'string'
+    ? new PublicKey(multisigAddress)
+    : multisigAddress;
+  const guardianPDA = getGuardianPDA(multisigPDA, guardianId);
+
+  const oldGuardianId = await findOwnerByRecoveryHash(connection, multisigPDA, recoveryHashIntermediate);
+  if (oldGuardianId === null) {
+    throw new Error("Mã khôi phục không khớp với owner nào của ví");
+  }
+  if (oldGuardianId === guardianId) {
+    throw new Error("Owner mới phải là một guardian khác có quyền khôi phục");
+  }
+
+  // Chương trình lưu khoá WebAuthn ở dạng nén 33 bytes
+  const compressedPubkey = compressPublicKey(Buffer.from(newWebauthnPubkey));
+
+  // Step 1: Đọc recovery_nonce của ví và nonce của guardian
+  const recoveryNonce = await fetchRecoveryNonce(connection, multisigPDA);
+  const recoveryRequestPDA = getRecoveryRequestPDA(multisigPDA, recoveryNonce);
+  const guardianInfo = await connection.getAccountInfo(guardianPDA);
+  if (!guardianInfo) {
+    throw new Error("Không tìm thấy tài khoản guardian");
+  }
+  const guardian = program.coder.accounts.decode("guardian", guardianInfo.data);
+
+  // Step 2: Ký SigningPayload của initiate_recovery bằng passkey
+  const newOwnerHash = toHex(sha256(compressedPubkey).slice(0, 6));
+  const payload = buildSigningPayload({
+    multisig: multisigPDA,
+    target: recoveryRequestPDA,
+    action: `initiate_recovery:old_${oldGuardianId},new_${guardianId},new_owner_${newOwnerHash}`,
+    guardianId,
+    nonce: guardian.nonce
+  });
+  const { secp256r1Instruction, assertion } = await signWithGuardianPasskey(
+    sha256(payload),
+    credentialId
+  );
+
+  // Step 3: Instruction secp256r1 kèm instruction initiate_recovery
+  const transaction = new Transaction();
+  transaction.add(secp256r1Instruction);
+  transaction.add(await program.methods
+    .initiateRecovery(
+      new BN(oldGuardianId),
+      new BN(guardianId),
+      new BN(guardianId),
+      Array.from(recoveryHashIntermediate),
+      Array.from(compressedPubkey),
+      assertion
+    )
+    .accountsPartial({
+      multisig: multisigPDA,
+      oldGuardian: getGuardianPDA(multisigPDA, oldGuardianId),
+      newGuardian: guardianPDA,
+      initiatorGuardian: guardianPDA,
+      guardianSigner: null,
+      recoveryRequest: recoveryRequestPDA,
+      payer: feePayerKeypair.publicKey,
+    })
+    .instruction());
+
+  const signature = await sendWithFeePayer(connection, transaction, feePayerKeypair);
+  console.log("Giao dịch tạo yêu cầu khôi phục đã được gửi:", signature);
+
+  return { signature, recoveryRequest: recoveryRequestPDA };
+};
+
+/**
+ * Guardian phê duyệt yêu cầu khôi phục đang chờ của ví bằng passkey của mình.
+ * Message được ký gắn với recovery_nonce và hash của khoá WebAuthn mới, giống approve_recovery
+ */
+export const handleApproveRecovery = async (
+  connection: Connection,
+  multisigAddress: PublicKey | string,
+  guardianId: number,
+  feePayerKeypair: Keypair,
+  credentialId?: string
+): Promise<string> => {
+  const multisigPDA = typeof multisigAddress === 'string'
+    ? new PublicKey(multisigAddress)
+    : multisigAddress;
+  const guardianPDA = getGuardianPDA(multisigPDA, guardianId);
+
+  // Step 1: Đọc yêu cầu khôi phục hiện tại và nonce của guardian
+  const recoveryRequestPDA = getRecoveryRequestPDA(
+    multisigPDA,
+    await fetchRecoveryNonce(connection, multisigPDA)
+  );
+  const [requestInfo, guardianInfo] = await connection.getMultipleAccountsInfo([
+    recoveryRequestPDA,
+    guardianPDA
+  ]);
+  if (!requestInfo) {
+    throw new Error("Ví không có yêu cầu khôi phục nào đang chờ");
+  }
+  if (!guardianInfo) {
+    throw new Error("Không tìm thấy tài khoản guardian");
+  }
+  const request = program.coder.accounts.decode("recoveryRequest", requestInfo.data);
+  const guardian = program.coder.accounts.decode("guardian", guardianInfo.data);
+
+  // Step 2: Challenge là sha256 của SigningPayload mà chương trình dựng lại khi xác thực
+  const newOwnerHash = toHex(sha256(Uint8Array.from(request.newWebauthnPubkey)).slice(0, 6));
+  const payload = buildSigningPayload({
+    multisig: multisigPDA,
+    target: recoveryRequestPDA,
+    action: `approve_recovery:recovery_${request.nonce.toString()},new_owner_${newOwnerHash}`,
+    guardianId,
+    nonce: guardian.nonce
+  });
+  const { secp256r1Instruction, assertion } = await signWithGuardianPasskey(
+    sha256(payload),
+    credentialId
+  );
+
+  // Step 3: Instruction secp256r1 kèm instruction approve_recovery
+  const transaction = new Transaction();
+  transaction.add(secp256r1Instruction);
+  transaction.add(await program.methods
+    .approveRecovery(new BN(guardianId), assertion)
+    .accountsPartial({
+      multisig: multisigPDA,
+      recoveryRequest: recoveryRequestPDA,
+      guardian: guardianPDA,
+      guardianSigner: null,
+    })
+    .instruction());
+
+  const txSignature = await sendWithFeePayer(connection, transaction, feePayerKeypair);
+  console.log("Giao dịch phê duyệt khôi phục đã được gửi:", txSignature);
+
+  return txSignature;
+};
@@ -1,10 +1,14 @@
-import { PublicKey, Transaction, TransactionInstruction, SystemProgram, SYSVAR_CLOCK_PUBKEY } from '@solana/web3.js';
+import { PublicKey, Transaction, TransactionInstruction, Connection } from '@solana/web3.js';
 import { Buffer } from 'buffer';
 import BN from 'bn.js';
+import { sha256 } from '@noble/hashes/sha256';
 import { PROGRAM_ID } from '@/utils/constants';
 import { createSecp256r1Instruction } from '@/utils/instructionUtils';
 import { normalizeSignatureToLowS } from '@/lib/solana/secp256r1';
 import { derToRaw } from '@/utils/bufferUtils';
+import { getGuardianPDA } from '@/utils/credentialUtils';
+import { buildSigningPayload } from '@/utils/proposalSigning';
+import { program } from '@/lib/solana';
 
 
 // Interface cho các tham số chung
@@ -15,6 +19,7 @@ interface BaseTransferParams {
   guardianId: number;
   destinationPubkey: PublicKey;
   description: string;
+  // ID mà chương trình sẽ cấp (multisig.next_proposal_id), lấy từ prepareCreateProposal
   proposalId: BN;
   webauthnSignature: Uint8Array;
   authenticatorData: Uint8Array;
@@ -84,18 +89,11 @@ export async function createSolTransferProposal(params: SolTransferParams): Prom
     false
   ));
 
-  // Tạo data cho create_proposal
-  const data = createProposalData({
-    proposalId: params.proposalId,
-    description: params.description,
-    guardianId: params.guardianId,
-    action: 'transfer',
-    amount: params.amountLamports,
-    destination: params.destinationPubkey,
-  });
-
   // Thêm instruction tạo proposal
-  tx.add(createProposalInstruction(params, data));
+  tx.add(await createProposalInstruction(
+    params,
+    buildSolTransferActions(params.amountLamports, params.destinationPubkey)
+  ));
 
   return tx;
 }
@@ -145,93 +143,98 @@ export async function createTokenTransferProposal(params: TokenTransferParams):
     false
   ));
 
-  // Tạo data cho create_proposal token transfer
-  const data = createProposalData({
-    proposalId: params.proposalId,
-    description: params.description,
-    guardianId: params.guardianId,
-    action: 'transfer_token',
-    tokenAmount: params.tokenAmount,
-    destination: params.destinationPubkey,
-    tokenMint: params.tokenMintPubkey
-  });
-
   // Thêm instruction tạo proposal
-  tx.add(createProposalInstruction(params, data));
+  tx.add(await createProposalInstruction(
+    params,
+    buildTokenTransferActions(params.tokenMintPubkey, params.tokenAmount, params.destinationPubkey)
+  ));
 
   return tx;
 }
 
-// Hàm helper để tạo data cho proposal
-function createProposalData(params: {
-  proposalId: BN;
-  description: string;
-  guardianId: number;
-  action: 'transfer' | 'transfer_token';
-  amount?: BN;
-  tokenAmount?: BN;
-  destination: PublicKey;
-  tokenMint?: PublicKey;
-}): Buffer {
-  const createProposalDiscriminator = [132, 116, 68, 174, 216, 160, 198, 22];
-  
-  const descriptionBuffer = Buffer.from(params.description);
-  const descriptionLenBuffer = Buffer.alloc(4);
-  descriptionLenBuffer.writeUInt32LE(descriptionBuffer.length, 0);
-  
-  // Sử dụng action string đúng như program yêu cầu (snake_case)
-  const actionBuffer = Buffer.from(params.action);
-  const actionLenBuffer = Buffer.alloc(4);
-  actionLenBuffer.writeUInt32LE(actionBuffer.length, 0);
+// Danh sách ProposalAction cho chuyển SOL, theo định dạng enum của IDL
+export function buildSolTransferActions(amountLamports: BN, destination: PublicKey): any[] {
+  return [{ transferSol: { amount: amountLamports, destination } }];
+}
 
-  const buffers = [
-    Buffer.from(createProposalDiscriminator),
-    Buffer.from(params.proposalId.toArrayLike(Buffer, 'le', 8)),
-    Buffer.from(descriptionLenBuffer),
-    descriptionBuffer,
-    Buffer.from(new BN(params.guardianId).toArrayLike(Buffer, 'le', 8)),
-    Buffer.from(actionLenBuffer),
-    actionBuffer,
-  ];
+// Danh sách ProposalAction cho chuyển Token, token được gửi vào ATA của destination
+export function buildTokenTransferActions(
+  tokenMint: PublicKey,
+  tokenAmount: BN,
+  destination: PublicKey
+): any[] {
+  return [{
+    transferToken: {
+      mint: tokenMint,
+      amount: tokenAmount,
+      destination,
+      recipientTokenAccount: null,
+    }
+  }];
+}
 
-  // Thêm các trường tùy theo loại action
-  if (params.action === 'transfer') {
-    buffers.push(
-      // amount (option<u64>): Some(amount)
-      Buffer.from([1]),
-      Buffer.from(params.amount!.toArrayLike(Buffer, 'le', 8)),
-      // destination (option<publicKey>): Some(destination)
-      Buffer.from([1]),
-      Buffer.from(params.destination.toBuffer()),
-      // token_mint (option<publicKey>): None
-      Buffer.from([0]),
-      // token_amount (option<u64>): None
-      Buffer.from([0])
-    );
-  } else {
-    buffers.push(
-      // amount (option<u64>): None
-      Buffer.from([0]),
-      // destination (option<publicKey>): Some(destination)
-      Buffer.from([1]),
-      Buffer.from(params.destination.toBuffer()),
-      // token_mint (option<publicKey>): Some(tokenMint)
-      Buffer.from([1]),
-      Buffer.from(params.tokenMint!.toBuffer()),
-      // token_amount (option<u64>): Some(tokenAmount)
-      Buffer.from([1]),
-      Buffer.from(params.tokenAmount!.toArrayLike(Buffer, 'le', 8))
-    );
+/**
+ * Hash nội dung đề xuất, phải khớp với TransactionProposal::compute_contents_hash:
+ * sha256(borsh((multisig, actions)))
+ */
+export function computeContentsHash(multisigPubkey: PublicKey, actions: any[]): Uint8Array {
+  const actionsLen = Buffer.alloc(4);
+  actionsLen.writeUInt32LE(actions.length, 0);
+
+  return sha256(Buffer.concat([
+    multisigPubkey.toBuffer(),
+    actionsLen,
+    ...actions.map((action) => program.coder.types.encode('proposalAction', action)),
+  ]));
+}
+
+/**
+ * Đọc ID đề xuất tiếp theo và nonce của guardian, rồi dựng challenge WebAuthn cho create_proposal.
+ * Chương trình tự cấp proposal ID nên client chỉ ký nội dung, không ký ID
+ */
+export async function prepareCreateProposal(
+  connection: Connection,
+  params: {
+    multisigPubkey: PublicKey;
+    guardianId: number;
+    actions: any[];
+  }
+): Promise<{ proposalId: BN; challenge: Uint8Array }> {
+  const guardianPDA = getGuardianPDA(params.multisigPubkey, params.guardianId);
+  const [multisigAccount, guardianAccount] = await connection.getMultipleAccountsInfo([
+    params.multisigPubkey,
+    guardianPDA
+  ]);
+  if (!multisigAccount) {
+    throw new Error('Không tìm thấy ví multisig');
   }
+  if (!guardianAccount) {
+    throw new Error('Không tìm thấy tài khoản guardian');
+  }
+
+  const multisigData = program.coder.accounts.decode('multiSigWallet', multisigAccount.data);
+  const guardianData = program.coder.accounts.decode('guardian', guardianAccount.data);
+
+  const contentsHash = computeContentsHash(params.multisigPubkey, params.actions);
+  const payload = buildSigningPayload({
+    multisig: params.multisigPubkey,
+    target: params.multisigPubkey,
+    action: `create:contents_${Buffer.from(contentsHash).toString('hex')}`,
+    guardianId: params.guardianId,
+    nonce: guardianData.nonce
+  });
 
-  return Buffer.concat(buffers);
+  return {
+    proposalId: new BN(multisigData.nextProposalId.toString()),
+    challenge: sha256(payload)
+  };
 }
 
-// Hàm helper để tạo instruction cho proposal
-function createProposalInstruction(
+// Hàm helper để tạo instruction cho proposal, dữ liệu được encode theo IDL
+async function createProposalInstruction(
   params: BaseTransferParams,
-  data: Buffer
-): TransactionInstruction {
+  actions: any[]
+): Promise<TransactionInstruction> {
   const [proposalPubkey] = PublicKey.findProgramAddressSync(
     [
       Buffer.from('proposal'),
@@ -241,16 +244,23 @@ function createProposalInstruction(
     PROGRAM_ID
   );
 
-  return new TransactionInstruction({
-    keys: [
-      { pubkey: params.multisigPubkey, isSigner: false, isWritable: true },
-      { pubkey: proposalPubkey, isSigner: false, isWritable: true },
-      { pubkey: params.guardianPubkey, isSigner: false, isWritable: false },
-      { pubkey: params.payerPublicKey, isSigner: true, isWritable: true },
-      { pubkey: SYSVAR_CLOCK_PUBKEY, isSigner: false, isWritable: false },
-      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
-    ],
-    programId: PROGRAM_ID,
-    data,
-  });
-} 
\ No newline at end of file
+  return await program.methods
+    .createProposal(
+      params.description,
+      new BN(params.guardianId),
+      actions,
+      null, // expires_at: dùng TTL mặc định của ví
+      {
+        authenticatorData: Buffer.from(params.authenticatorData),
+        clientDataJson: Buffer.from(params.clientDataJSON),
+      }
+    )
+    .accountsPartial({
+      multisig: params.multisigPubkey,
+      proposal: proposalPubkey,
+      proposerGuardian: params.guardianPubkey,
+      guardianSigner: null,
+      payer: params.payerPublicKey,
+    })
+    .instruction();
+}
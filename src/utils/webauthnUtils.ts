@@ -410,7 +410,7 @@ function createAllowCredentialsOptions(
  */
 export const getWebAuthnAssertion = async (
   credentialId?: string,
-  message?: string,
+  message?: string | Uint8Array,
   allowEmpty: boolean = false
 ): Promise<{
   signature: Uint8Array;
@@ -422,10 +422,12 @@ export const getWebAuthnAssertion = async (
     throw new Error("WebAuthn is not supported in this browser");
   }
 
-  // Create challenge from message or random
-  const challenge = message 
-    ? new TextEncoder().encode(message)
-    : crypto.getRandomValues(new Uint8Array(32));
+  // Create challenge from message or random; raw bytes are used as-is (e.g. a signing payload hash)
+  const challenge = message instanceof Uint8Array
+    ? message
+    : message
+      ? new TextEncoder().encode(message)
+      : crypto.getRandomValues(new Uint8Array(32));
 
   // Read credentials from localStorage
   const credentials = getCredentialsFromLocalStorage();
@@ -435,7 +437,8 @@ export const getWebAuthnAssertion = async (
     challenge,
     rpId: window.location.hostname,
     timeout: 60000,
-    userVerification: "preferred",
+    // The program rejects assertions without the user-verified flag
+    userVerification: "required",
     allowCredentials: createAllowCredentialsOptions(credentialId, allowEmpty, credentials)
   };
 